@@ -3,6 +3,9 @@ use std::sync::atomic::AtomicUsize;
 
 static CMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
+// only Ord::cmp is counted, the derived PartialOrd used by `<` and `>=`
+// stays out of the count on purpose
+#[allow(clippy::derive_ord_xor_partial_ord)]
 #[derive(PartialEq, Eq, PartialOrd, Clone, Copy)]
 struct Key {
     value: i32,
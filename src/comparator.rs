@@ -0,0 +1,24 @@
+/// ordering of keys used by tree, so keys can be ordered by something other
+/// than their `Ord`
+use std::cmp::Ordering;
+
+pub trait Comparator<K: ?Sized> {
+    fn cmp(&self, a: &K, b: &K) -> Ordering;
+}
+
+/// order keys by their `Ord`, the default comparator
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OrdComparator;
+
+impl<K: Ord + ?Sized> Comparator<K> for OrdComparator {
+    fn cmp(&self, a: &K, b: &K) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// closures are comparators, they can capture runtime parameters
+impl<K: ?Sized, F: Fn(&K, &K) -> Ordering> Comparator<K> for F {
+    fn cmp(&self, a: &K, b: &K) -> Ordering {
+        self(a, b)
+    }
+}
@@ -0,0 +1,683 @@
+/// Cursor api, a cursor points at a key value or at the "ghost" position
+/// after the last key value. The ghost sits between the last and the first
+/// key value, moving past either end reaches it, moving on wraps around.
+use crate::node::iter::seek_front;
+use crate::node::node::Node;
+use crate::{BTree, Comparator, OrdComparator, Summary};
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+/// stack of (node, idx) from root to the node holding current key value.
+/// For ancestors, idx is the child descended into, which is also the key
+/// value after that child, same as the front stack of `Iter`. Empty at ghost.
+type Stack<'a, K, V, S> = Vec<(&'a Node<K, V, S>, usize)>;
+
+pub struct Cursor<'a, K, V, S = ()> {
+    root: Option<&'a Node<K, V, S>>,
+    stack: Stack<'a, K, V, S>,
+    /// offset of current key value, count of tree at ghost
+    offset: usize,
+}
+
+impl<K, V, S> Clone for Cursor<'_, K, V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root,
+            stack: self.stack.clone(),
+            offset: self.offset,
+        }
+    }
+}
+
+impl<'a, K, V, S> Cursor<'a, K, V, S> {
+    /// cursor at offset, ghost if offset is out of tree
+    pub(crate) fn new(root: Option<&'a Node<K, V, S>>, offset: usize) -> Self {
+        let mut cursor = Self {
+            root,
+            stack: vec![],
+            offset: 0,
+        };
+        cursor.seek(offset);
+        cursor
+    }
+
+    /// cursor at the key value located by path and idx
+    fn new_at_path(root: &'a Node<K, V, S>, path: &[usize], idx: usize, offset: usize) -> Self {
+        let mut stack = Vec::with_capacity(path.len() + 1);
+        let mut node = root;
+        for child_idx in path {
+            stack.push((node, *child_idx));
+            node = &node.children[*child_idx];
+        }
+        stack.push((node, idx));
+        Self {
+            root: Some(root),
+            stack,
+            offset,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.root.map_or(0, |root| root.count)
+    }
+
+    fn seek(&mut self, offset: usize) {
+        self.stack.clear();
+        match self.root {
+            Some(root) if offset < root.count => {
+                seek_front(&mut self.stack, root, offset);
+                self.offset = offset;
+            }
+            _ => self.offset = self.len(),
+        }
+    }
+
+    pub fn key_value(&self) -> Option<&'a (K, V)> {
+        let (node, idx) = *self.stack.last()?;
+        Some(&node.key_values[idx])
+    }
+
+    pub fn key(&self) -> Option<&'a K> {
+        self.key_value().map(|(k, _)| k)
+    }
+
+    pub fn value(&self) -> Option<&'a V> {
+        self.key_value().map(|(_, v)| v)
+    }
+
+    /// offset of current key value, None at ghost
+    pub fn offset(&self) -> Option<usize> {
+        if self.stack.is_empty() {
+            None
+        } else {
+            Some(self.offset)
+        }
+    }
+
+    /// move to next key value, from the last one to ghost, from ghost to the
+    /// first one
+    pub fn move_next(&mut self) {
+        if self.stack.is_empty() {
+            self.seek(0);
+            return;
+        }
+
+        self.offset += 1;
+        let (node, idx) = *self.stack.last().unwrap();
+        if !node.is_leaf() {
+            // left most key value of the right child
+            self.stack.last_mut().unwrap().1 = idx + 1;
+            let mut child: &Node<K, V, S> = &node.children[idx + 1];
+            loop {
+                self.stack.push((child, 0));
+                if child.is_leaf() {
+                    return;
+                }
+                child = &child.children[0];
+            }
+        }
+
+        if idx + 1 < node.key_values.len() {
+            self.stack.last_mut().unwrap().1 = idx + 1;
+            return;
+        }
+
+        // climb until an ancestor has a key value after the child
+        self.stack.pop();
+        while let Some((node, idx)) = self.stack.last() {
+            if *idx < node.key_values.len() {
+                return;
+            }
+            self.stack.pop();
+        }
+    }
+
+    /// move to previous key value, from the first one to ghost, from ghost to
+    /// the last one
+    pub fn move_prev(&mut self) {
+        if self.stack.is_empty() {
+            self.seek(self.len().wrapping_sub(1));
+            return;
+        }
+        if self.offset == 0 {
+            self.seek(self.len());
+            return;
+        }
+
+        self.offset -= 1;
+        let (node, idx) = *self.stack.last().unwrap();
+        if !node.is_leaf() {
+            // right most key value of the left child
+            let mut child: &Node<K, V, S> = &node.children[idx];
+            loop {
+                if child.is_leaf() {
+                    self.stack.push((child, child.key_values.len() - 1));
+                    return;
+                }
+                self.stack.push((child, child.key_values.len()));
+                child = child.children.last().unwrap();
+            }
+        }
+
+        if idx > 0 {
+            self.stack.last_mut().unwrap().1 = idx - 1;
+            return;
+        }
+
+        // climb until an ancestor has a key value before the child
+        self.stack.pop();
+        while let Some((_, idx)) = self.stack.last_mut() {
+            if *idx > 0 {
+                *idx -= 1;
+                return;
+            }
+            self.stack.pop();
+        }
+    }
+
+    /// key value after current one without moving, the first one at ghost
+    pub fn peek_next(&self) -> Option<&'a (K, V)> {
+        let (node, idx) = match self.stack.last() {
+            Some(top) => *top,
+            None => return left_most(self.root?),
+        };
+
+        if !node.is_leaf() {
+            return left_most(&node.children[idx + 1]);
+        }
+
+        if idx + 1 < node.key_values.len() {
+            return Some(&node.key_values[idx + 1]);
+        }
+
+        let ancestors = &self.stack[..self.stack.len() - 1];
+        ancestors
+            .iter()
+            .rev()
+            .find_map(|(node, idx)| node.key_values.get(*idx))
+    }
+
+    /// key value before current one without moving, the last one at ghost
+    pub fn peek_prev(&self) -> Option<&'a (K, V)> {
+        let (node, idx) = match self.stack.last() {
+            Some(top) => *top,
+            None => return right_most(self.root?),
+        };
+
+        if !node.is_leaf() {
+            return right_most(&node.children[idx]);
+        }
+
+        if idx > 0 {
+            return Some(&node.key_values[idx - 1]);
+        }
+
+        let ancestors = &self.stack[..self.stack.len() - 1];
+        ancestors
+            .iter()
+            .rev()
+            .find(|(_, idx)| *idx > 0)
+            .map(|(node, idx)| &node.key_values[idx - 1])
+    }
+
+    /// child indexes and idx of current key value, None at ghost
+    pub(crate) fn path(&self) -> Option<(Vec<usize>, usize)> {
+        let (_, idx) = self.stack.last()?;
+        let path = self.stack[..self.stack.len() - 1]
+            .iter()
+            .map(|(_, child_idx)| *child_idx)
+            .collect();
+        Some((path, *idx))
+    }
+}
+
+/// A cursor which can modify the tree. It keeps the child indexes to current
+/// key value instead of node references, reads and writes follow them
+/// without comparing keys. Nodes on the path are copied on write if shared
+/// with snapshots.
+///
+/// Unlike `Cursor` there is no node stack: every call walks the indexes down
+/// from the root, O(height) without allocating. Holding the nodes would make
+/// the cursor an extra owner of them, so each write through it would copy the
+/// whole path. Moves step the indexes in place, they are derived again by
+/// offset only after an insert splits or a remove merges nodes.
+pub struct CursorMut<'a, K, V, C = OrdComparator, S = ()> {
+    pub(crate) tree: &'a mut BTree<K, V, C, S>,
+    /// child indexes and idx of current key value, None at ghost
+    pub(crate) path: Option<(Vec<usize>, usize)>,
+    pub(crate) offset: usize,
+}
+
+impl<'a, K: Clone, V: Clone, C: Comparator<K> + Clone, S: Summary<K, V>> CursorMut<'a, K, V, C, S> {
+    pub(crate) fn new(tree: &'a mut BTree<K, V, C, S>, offset: usize) -> Self {
+        let mut cursor = Self {
+            tree,
+            path: None,
+            offset: 0,
+        };
+        cursor.seek(offset);
+        cursor
+    }
+
+    /// read only cursor at the same position
+    pub fn as_cursor(&self) -> Cursor<'_, K, V, S> {
+        match (self.tree.root.as_deref(), self.path.as_ref()) {
+            (Some(root), Some((path, idx))) => Cursor::new_at_path(root, path, *idx, self.offset),
+            (root, _) => Cursor::new(root, self.offset),
+        }
+    }
+
+    pub fn key_value(&self) -> Option<&(K, V)> {
+        let (path, idx) = self.path.as_ref()?;
+        let root = self.tree.root.as_deref()?;
+        Some(&node_at_path(root, path).key_values[*idx])
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        self.key_value().map(|(k, _)| k)
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        self.key_value().map(|(_, v)| v)
+    }
+
+    /// offset of current key value, None at ghost
+    pub fn offset(&self) -> Option<usize> {
+        self.path.as_ref().map(|_| self.offset)
+    }
+
+    /// move to next key value, from the last one to ghost, from ghost to the
+    /// first one
+    pub fn move_next(&mut self) {
+        let root = match self.tree.root.as_deref() {
+            Some(root) if root.count > 0 => root,
+            _ => return,
+        };
+        match self.path.as_mut() {
+            None => {
+                self.path = Some((vec![0; root.height()], 0));
+                self.offset = 0;
+            }
+            Some((path, idx)) => {
+                self.offset += 1;
+                if !step_next(root, path, idx) {
+                    self.path = None;
+                }
+            }
+        }
+    }
+
+    /// move to previous key value, from the first one to ghost, from ghost to
+    /// the last one
+    pub fn move_prev(&mut self) {
+        let root = match self.tree.root.as_deref() {
+            Some(root) if root.count > 0 => root,
+            _ => return,
+        };
+        match self.path.as_mut() {
+            None => {
+                let (path, idx) = root.right_most_path();
+                self.path = Some((path, idx - 1));
+                self.offset = root.count - 1;
+            }
+            Some((path, idx)) => {
+                if step_prev(root, path, idx) {
+                    self.offset -= 1;
+                } else {
+                    self.path = None;
+                    self.offset = root.count;
+                }
+            }
+        }
+    }
+
+    /// key value after current one without moving, the first one at ghost
+    pub fn peek_next(&self) -> Option<&(K, V)> {
+        let root = self.tree.root.as_deref()?;
+        let (path, idx) = match self.path.as_ref() {
+            Some((path, idx)) => (path, *idx),
+            None => return left_most(root),
+        };
+
+        let node = node_at_path(root, path);
+        if !node.is_leaf() {
+            return left_most(&node.children[idx + 1]);
+        }
+        if idx + 1 < node.key_values.len() {
+            return Some(&node.key_values[idx + 1]);
+        }
+        next_ancestor(root, path).map(|(level, node)| &node.key_values[path[level]])
+    }
+
+    /// key value before current one without moving, the last one at ghost
+    pub fn peek_prev(&self) -> Option<&(K, V)> {
+        let root = self.tree.root.as_deref()?;
+        let (path, idx) = match self.path.as_ref() {
+            Some((path, idx)) => (path, *idx),
+            None => return right_most(root),
+        };
+
+        let node = node_at_path(root, path);
+        if !node.is_leaf() {
+            return right_most(&node.children[idx]);
+        }
+        if idx > 0 {
+            return Some(&node.key_values[idx - 1]);
+        }
+        prev_ancestor(root, path).map(|(level, node)| &node.key_values[path[level] - 1])
+    }
+
+    /// insert key value before current one, at ghost it becomes the last
+    /// one. Cursor stays at current key value.
+    ///
+    /// # Panics
+    /// Panics if key is not between previous key and current key.
+    pub fn insert_before(&mut self, key: K, value: V) {
+        self.check_order(self.peek_prev(), &key, self.key_value());
+
+        let gap = match (self.tree.root.as_deref(), self.path.as_ref()) {
+            (None, _) => None,
+            (Some(root), None) => Some(root.right_most_path()),
+            (Some(root), Some((path, idx))) => {
+                let node = node_at_path(root, path);
+                if node.is_leaf() {
+                    Some((path.clone(), *idx))
+                } else {
+                    // after the right most key value of the left child
+                    let (child_path, child_idx) = node.children[*idx].right_most_path();
+                    let mut gap_path = path.clone();
+                    gap_path.push(*idx);
+                    gap_path.extend(child_path);
+                    Some((gap_path, child_idx))
+                }
+            }
+        };
+
+        // current key value shifts right in its leaf if the gap is there
+        let same_leaf = matches!(
+            (&gap, &self.path),
+            (Some((gap_path, _)), Some((path, _))) if gap_path == path
+        );
+        let offset = self.offset + 1;
+        if self.insert_at_gap(gap, key, value) {
+            self.seek(offset);
+            return;
+        }
+        self.offset = offset;
+        if let Some((_, idx)) = self.path.as_mut().filter(|_| same_leaf) {
+            *idx += 1;
+        }
+    }
+
+    /// insert key value after current one, at ghost it becomes the first
+    /// one. Cursor stays at current key value.
+    ///
+    /// # Panics
+    /// Panics if key is not between current key and next key.
+    pub fn insert_after(&mut self, key: K, value: V) {
+        self.check_order(self.key_value(), &key, self.peek_next());
+
+        let gap = match (self.tree.root.as_deref(), self.path.as_ref()) {
+            (None, _) => None,
+            (Some(root), None) => Some((vec![0; root.height()], 0)),
+            (Some(root), Some((path, idx))) => {
+                let node = node_at_path(root, path);
+                if node.is_leaf() {
+                    Some((path.clone(), *idx + 1))
+                } else {
+                    // before the left most key value of the right child
+                    let mut gap_path = path.clone();
+                    gap_path.push(*idx + 1);
+                    gap_path.extend(vec![0; node.children[*idx + 1].height()]);
+                    Some((gap_path, 0))
+                }
+            }
+        };
+
+        let restructured = self.insert_at_gap(gap, key, value);
+        if self.path.is_none() {
+            self.offset = self.tree.len();
+        } else if restructured {
+            self.seek(self.offset);
+        }
+    }
+
+    /// remove current key value, cursor moves to the next one. None at ghost.
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        let (mut path, mut idx) = self.path.take()?;
+
+        // path to the leaf losing a key value, for a branch it is the one
+        // holding the previous key value which replaces the removed one
+        let root = self.tree.root.as_deref().unwrap();
+        let node = node_at_path(root, &path);
+        let is_leaf = node.is_leaf();
+        let mut leaf_path = path.clone();
+        if !is_leaf {
+            leaf_path.push(idx);
+            leaf_path.extend(node.children[idx].right_most_path().0);
+        }
+        let lens = key_value_lens(root, &leaf_path);
+
+        let root = Arc::make_mut(self.tree.root.as_mut().unwrap());
+        let key_value = root.delete_at_path(&path, idx, &self.tree.config);
+
+        // merges and rotations change the size of some node on leaf path
+        let mut expected = lens;
+        *expected.last_mut().unwrap() -= 1;
+        let restructured = key_value_lens(root, &leaf_path) != expected;
+        self.tree.shrink_root();
+
+        if restructured {
+            self.seek(self.offset);
+        } else if let Some(root) = self.tree.root.as_deref() {
+            let at_next = if is_leaf {
+                // next key value is the one shifted into idx, or above
+                idx < node_at_path(root, &path).key_values.len()
+                    || step_next(root, &mut path, &mut idx)
+            } else {
+                // next key value is the left most one of the right child
+                path.push(idx + 1);
+                path.extend(vec![0; leaf_path.len() - path.len()]);
+                idx = 0;
+                true
+            };
+            if at_next {
+                self.path = Some((path, idx));
+            } else {
+                self.offset = root.count;
+            }
+        }
+        Some(key_value)
+    }
+
+    fn seek(&mut self, offset: usize) {
+        let cursor = Cursor::new(self.tree.root.as_deref(), offset);
+        let (path, offset) = (cursor.path(), cursor.offset);
+        self.path = path;
+        self.offset = offset;
+    }
+
+    /// insert at leaf gap located by path and idx, None if tree is empty.
+    /// Returns true if nodes were split, caller should seek again then.
+    fn insert_at_gap(&mut self, gap: Option<(Vec<usize>, usize)>, key: K, value: V) -> bool {
+        match gap {
+            Some((path, idx)) => {
+                let root = Arc::make_mut(self.tree.root.as_mut().unwrap());
+                let leaf_len = node_at_path(root, &path).key_values.len();
+                let insert_result = root.insert_at_path(&path, idx, key, value, &self.tree.config);
+                self.tree.grow_root(insert_result);
+                self.tree.config.node_should_split(leaf_len + 1)
+            }
+            None => {
+                self.tree.insert(key, value);
+                false
+            }
+        }
+    }
+
+    fn check_order(&self, prev: Option<&(K, V)>, key: &K, next: Option<&(K, V)>) {
+        let comparator = &self.tree.comparator;
+        if let Some((prev, _)) = prev {
+            assert!(
+                comparator.cmp(prev, key) == Ordering::Less,
+                "key must be greater than the key before it"
+            );
+        }
+        if let Some((next, _)) = next {
+            assert!(
+                comparator.cmp(key, next) == Ordering::Less,
+                "key must be less than the key after it"
+            );
+        }
+    }
+}
+
+/// Mutable access to values, only for trees without summary, see
+/// `BTree::get_mut`
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone> CursorMut<'_, K, V, C> {
+    /// mutable reference to current value, nodes on the path are copied if
+    /// shared
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        let (path, idx) = self.path.as_ref()?;
+        let root = Arc::make_mut(self.tree.root.as_mut()?);
+        Some(&mut root.get_mut_at_path(path, *idx).1)
+    }
+}
+
+fn node_at_path<'a, K, V, S>(root: &'a Node<K, V, S>, path: &[usize]) -> &'a Node<K, V, S> {
+    let mut node = root;
+    for child_idx in path {
+        node = &node.children[*child_idx];
+    }
+    node
+}
+
+fn left_most<K, V, S>(node: &Node<K, V, S>) -> Option<&(K, V)> {
+    let mut node = node;
+    while !node.is_leaf() {
+        node = &node.children[0];
+    }
+    node.key_values.first()
+}
+
+fn right_most<K, V, S>(node: &Node<K, V, S>) -> Option<&(K, V)> {
+    let mut node = node;
+    while !node.is_leaf() {
+        node = node.children.last().unwrap();
+    }
+    node.key_values.last()
+}
+
+/// key value count of each node on path, root first. Stops early if path
+/// leads out of the tree.
+fn key_value_lens<K, V, S>(root: &Node<K, V, S>, path: &[usize]) -> Vec<usize> {
+    let mut lens = Vec::with_capacity(path.len() + 1);
+    let mut node = root;
+    lens.push(node.key_values.len());
+    for child_idx in path {
+        match node.children.get(*child_idx) {
+            Some(child) => node = child,
+            None => break,
+        }
+        lens.push(node.key_values.len());
+    }
+    lens
+}
+
+/// level and node of the deepest ancestor on path which has a key value
+/// after the child descended into
+fn next_ancestor<'a, K, V, S>(
+    root: &'a Node<K, V, S>,
+    path: &[usize],
+) -> Option<(usize, &'a Node<K, V, S>)> {
+    let mut node = root;
+    let mut found = None;
+    for (level, child_idx) in path.iter().enumerate() {
+        if *child_idx < node.key_values.len() {
+            found = Some((level, node));
+        }
+        node = &node.children[*child_idx];
+    }
+    found
+}
+
+/// level and node of the deepest ancestor on path which has a key value
+/// before the child descended into
+fn prev_ancestor<'a, K, V, S>(
+    root: &'a Node<K, V, S>,
+    path: &[usize],
+) -> Option<(usize, &'a Node<K, V, S>)> {
+    let mut node = root;
+    let mut found = None;
+    for (level, child_idx) in path.iter().enumerate() {
+        if *child_idx > 0 {
+            found = Some((level, node));
+        }
+        node = &node.children[*child_idx];
+    }
+    found
+}
+
+/// step path and idx to the next key value in place, same as
+/// `Cursor::move_next`. Returns false if current one is the last.
+fn step_next<K, V, S>(root: &Node<K, V, S>, path: &mut Vec<usize>, idx: &mut usize) -> bool {
+    let node = node_at_path(root, path);
+    if !node.is_leaf() {
+        // left most key value of the right child
+        path.push(*idx + 1);
+        let mut child: &Node<K, V, S> = &node.children[*idx + 1];
+        while !child.is_leaf() {
+            path.push(0);
+            child = &child.children[0];
+        }
+        *idx = 0;
+        return true;
+    }
+
+    if *idx + 1 < node.key_values.len() {
+        *idx += 1;
+        return true;
+    }
+
+    // climb to the deepest ancestor having a key value after the child
+    match next_ancestor(root, path) {
+        Some((level, _)) => {
+            *idx = path[level];
+            path.truncate(level);
+            true
+        }
+        None => false,
+    }
+}
+
+/// step path and idx to the previous key value in place, same as
+/// `Cursor::move_prev`. Returns false if current one is the first.
+fn step_prev<K, V, S>(root: &Node<K, V, S>, path: &mut Vec<usize>, idx: &mut usize) -> bool {
+    let node = node_at_path(root, path);
+    if !node.is_leaf() {
+        // right most key value of the left child
+        path.push(*idx);
+        let mut child: &Node<K, V, S> = &node.children[*idx];
+        while !child.is_leaf() {
+            path.push(child.children.len() - 1);
+            child = child.children.last().unwrap();
+        }
+        *idx = child.key_values.len() - 1;
+        return true;
+    }
+
+    if *idx > 0 {
+        *idx -= 1;
+        return true;
+    }
+
+    // climb to the deepest ancestor having a key value before the child
+    match prev_ancestor(root, path) {
+        Some((level, _)) => {
+            *idx = path[level] - 1;
+            path.truncate(level);
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,161 @@
+/// Entry api, the key is located once by `BTree::entry`, later reads and
+/// writes follow the recorded path of child indexes without comparing keys
+use crate::node::insert::{slot_after_split, InsertResult};
+use crate::{BTree, Comparator, OrdComparator};
+use std::sync::Arc;
+
+pub enum Entry<'a, K, V, C = OrdComparator> {
+    Occupied(OccupiedEntry<'a, K, V, C>),
+    Vacant(VacantEntry<'a, K, V, C>),
+}
+
+pub struct OccupiedEntry<'a, K, V, C = OrdComparator> {
+    pub(crate) tree: &'a mut BTree<K, V, C>,
+    pub(crate) path: Vec<usize>,
+    pub(crate) idx: usize,
+}
+
+pub struct VacantEntry<'a, K, V, C = OrdComparator> {
+    pub(crate) tree: &'a mut BTree<K, V, C>,
+    pub(crate) key: K,
+    pub(crate) path: Vec<usize>,
+    pub(crate) idx: usize,
+}
+
+impl<'a, K: Clone, V: Clone, C: Comparator<K> + Clone> Entry<'a, K, V, C> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// insert value if vacant, returns mutable reference to the value
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(value),
+        }
+    }
+
+    /// insert value returned by f if vacant, returns mutable reference to the
+    /// value
+    pub fn or_insert_with(self, f: impl FnOnce() -> V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// modify value in place if occupied
+    #[must_use]
+    pub fn and_modify(self, f: impl FnOnce(&mut V)) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K: Clone, V: Clone + Default, C: Comparator<K> + Clone> Entry<'a, K, V, C> {
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Clone, V: Clone, C: Comparator<K> + Clone> OccupiedEntry<'a, K, V, C> {
+    pub fn key(&self) -> &K {
+        &self.key_value().0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.key_value().1
+    }
+
+    /// mutable reference to value, nodes on the path are copied if shared
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.key_value_mut().1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        let root = Arc::make_mut(self.tree.root.as_mut().unwrap());
+        &mut root.get_mut_at_path(&self.path, self.idx).1
+    }
+
+    /// replace value, returns the old one
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// remove the entry from tree, returns the value
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// remove the entry from tree, returns the key and value
+    pub fn remove_entry(self) -> (K, V) {
+        let root = Arc::make_mut(self.tree.root.as_mut().unwrap());
+        let key_value = root.delete_at_path(&self.path, self.idx, &self.tree.config);
+        self.tree.shrink_root();
+        key_value
+    }
+
+    fn key_value(&self) -> &(K, V) {
+        let mut node = self.tree.root.as_ref().unwrap();
+        for child_idx in self.path.iter() {
+            node = &node.children[*child_idx];
+        }
+        &node.key_values[self.idx]
+    }
+
+    fn key_value_mut(&mut self) -> &mut (K, V) {
+        let root = Arc::make_mut(self.tree.root.as_mut().unwrap());
+        root.get_mut_at_path(&self.path, self.idx)
+    }
+}
+
+impl<'a, K: Clone, V: Clone, C: Comparator<K> + Clone> VacantEntry<'a, K, V, C> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// insert value with entry's key, returns mutable reference to the value
+    pub fn insert(self, value: V) -> &'a mut V {
+        let tree = self.tree;
+        let (path, idx) = match tree.root.as_mut() {
+            Some(root) => {
+                let root = Arc::make_mut(root);
+                let (insert_result, slot) = root.insert_at_path_with_slot(
+                    &self.path,
+                    self.idx,
+                    self.key,
+                    value,
+                    &tree.config,
+                );
+                // a splitted root becomes the children of the new root
+                let slot = match &insert_result {
+                    InsertResult::Splited { new_l, .. } => {
+                        slot_after_split(0, new_l.key_values.len(), slot)
+                    }
+                    InsertResult::NotSplited { .. } => slot,
+                };
+                tree.grow_root(insert_result);
+                slot
+            }
+            None => {
+                tree.insert(self.key, value);
+                (vec![], 0)
+            }
+        };
+
+        let root = Arc::make_mut(tree.root.as_mut().unwrap());
+        &mut root.get_mut_at_path(&path, idx).1
+    }
+}
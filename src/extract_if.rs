@@ -0,0 +1,45 @@
+/// lazy removal by predicate, see `BTree::extract_if`
+use crate::{BTree, Comparator, Summary};
+
+/// iterator which removes and yields key values matching a predicate. The
+/// tree is scanned by offset, removal keeps the offset of the next key value.
+pub struct ExtractIf<'a, K, V, C, F, S = ()> {
+    pub(crate) tree: &'a mut BTree<K, V, C, S>,
+    pub(crate) offset: usize,
+    pub(crate) pred: F,
+}
+
+impl<K, V, C, F, S> Iterator for ExtractIf<'_, K, V, C, F, S>
+where
+    K: Clone,
+    V: Clone,
+    C: Comparator<K> + Clone,
+    F: FnMut(&K, &V) -> bool,
+    S: Summary<K, V>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((key, value)) = self.tree.get_by_offset(self.offset) {
+            if (self.pred)(key, value) {
+                return self.tree.delete_by_offset(self.offset);
+            }
+            self.offset += 1;
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.tree.len().saturating_sub(self.offset)))
+    }
+}
+
+impl<K, V, C, F, S> std::iter::FusedIterator for ExtractIf<'_, K, V, C, F, S>
+where
+    K: Clone,
+    V: Clone,
+    C: Comparator<K> + Clone,
+    F: FnMut(&K, &V) -> bool,
+    S: Summary<K, V>,
+{
+}
@@ -0,0 +1,41 @@
+/// owning iterator of a tree
+use crate::{BTree, Comparator, OrdComparator, Summary};
+
+/// iterator which takes key values out of the tree from both ends. Nodes
+/// owned only by this tree are consumed in place, nodes shared with other
+/// trees are copied when first touched.
+pub struct IntoIter<K, V, C = OrdComparator, S = ()> {
+    pub(crate) tree: BTree<K, V, C, S>,
+}
+
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone, S: Summary<K, V>> Iterator
+    for IntoIter<K, V, C, S>
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tree.pop_first()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.tree.len(), Some(self.tree.len()))
+    }
+}
+
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone, S: Summary<K, V>> DoubleEndedIterator
+    for IntoIter<K, V, C, S>
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.tree.pop_last()
+    }
+}
+
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone, S: Summary<K, V>> ExactSizeIterator
+    for IntoIter<K, V, C, S>
+{
+}
+
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone, S: Summary<K, V>> std::iter::FusedIterator
+    for IntoIter<K, V, C, S>
+{
+}
@@ -1,10 +1,28 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
 use std::fmt::Debug;
+use std::ops::{Bound, RangeBounds};
 use std::sync::Arc;
 
+pub use comparator::{Comparator, OrdComparator};
+pub use cursor::{Cursor, CursorMut};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use extract_if::ExtractIf;
+pub use into_iter::IntoIter;
+use node::algebra::SetOp;
+pub use node::build::SortedIterError;
+pub use node::diff::{Diff, DiffItem, MergeConflict};
 pub use node::find::*;
 use node::insert::InsertResult;
-use node::node::Node;
+pub use node::iter::{Iter, Keys, Values};
+use node::join::Subtree;
+use node::node::{KeyPosition, Node};
+use node::split::SplitResult;
 pub use node::visit;
+pub use rope::{Chunks, Rope, TextSummary};
+pub use sequence::Sequence;
+pub use set::BTreeSet;
+pub use summary::{Field, Max, Measure, Measured, Min, Sum, Summary};
 
 #[derive(Debug, Clone, Copy)]
 pub struct BTreeConfig {
@@ -42,172 +60,2932 @@ impl BTreeConfig {
     }
 }
 
-pub struct BTree<K, V> {
-    root: Option<Arc<Node<K, V>>>,
+pub struct BTree<K, V, C = OrdComparator, S = ()> {
+    root: Option<Arc<Node<K, V, S>>>,
     config: BTreeConfig,
+    comparator: C,
 }
 
-impl<K: Debug, V: Debug> Debug for BTree<K, V> {
+impl<K: Debug, V: Debug, C, S> Debug for BTree<K, V, C, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("BTree").field("root", &self.root).finish()
     }
 }
 
-impl<K: Ord + Clone, V: Clone> Default for BTree<K, V> {
+/// Cloning only bumps the root's ref count, nodes are shared between the copies
+/// and copied lazily on write.
+impl<K, V, C: Clone, S> Clone for BTree<K, V, C, S> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            config: self.config,
+            comparator: self.comparator.clone(),
+        }
+    }
+}
+
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone + Default, S: Summary<K, V>> Default
+    for BTree<K, V, C, S>
+{
     fn default() -> Self {
-        Self::new()
+        Self::new_with_summary(Self::default_config(), C::default())
     }
 }
 
+/// trees ordered by key's `Ord`
 impl<K: Ord + Clone, V: Clone> BTree<K, V> {
     /// create a new tree with default max_degree
     pub fn new() -> Self {
-        Self::new_with_config(BTreeConfig {
-            max_degree: std::cmp::max(20, 4096 / std::mem::size_of::<(K, V)>()),
-        })
+        Self::new_with_config(Self::default_config())
     }
 
     pub fn new_with_config(config: BTreeConfig) -> Self {
-        Self { root: None, config }
+        Self::new_with_comparator(config, OrdComparator)
+    }
+
+    /// build tree from key values sorted by key in O(n), nodes are filled
+    /// bottom up. Returns error if keys are not strictly ascending.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::{BTree, BTreeConfig, SortedIterError};
+    ///
+    /// let config = BTreeConfig { max_degree: 8 };
+    /// let tree = BTree::from_sorted_iter((0..100).map(|i| (i, i * 2)), config).unwrap();
+    /// assert_eq!(*tree.get_by_key(&42).unwrap(), 84);
+    ///
+    /// let result = BTree::from_sorted_iter([(2, ()), (1, ())], config);
+    /// assert_eq!(result.unwrap_err(), SortedIterError::Unsorted { offset: 1 });
+    /// ```
+    pub fn from_sorted_iter(
+        iter: impl IntoIterator<Item = (K, V)>,
+        config: BTreeConfig,
+    ) -> Result<Self, SortedIterError> {
+        Self::from_sorted_iter_with_comparator(iter, config, OrdComparator)
+    }
+}
+
+/// trees without summary. Mutable references to values are only handed out
+/// here, since summaries on the path can't be refreshed once released.
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone> BTree<K, V, C> {
+    /// create a new tree ordered by comparator instead of key's `Ord`. Any
+    /// `Fn(&K, &K) -> Ordering` closure is a comparator, it may capture
+    /// state, e.g. a collation chosen at runtime.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::{BTree, BTreeConfig};
+    ///
+    /// let config = BTreeConfig { max_degree: 8 };
+    /// let mut tree = BTree::new_with_comparator(config, |a: &i32, b: &i32| b.cmp(a));
+    /// for i in 0..5 {
+    ///     tree.insert(i, ());
+    /// }
+    /// assert_eq!(tree.keys().copied().collect::<Vec<_>>(), vec![4, 3, 2, 1, 0]);
+    /// ```
+    pub fn new_with_comparator(config: BTreeConfig, comparator: C) -> Self {
+        Self::new_with_summary(config, comparator)
+    }
+
+    /// same as `from_sorted_iter`, keys must be strictly ascending by
+    /// comparator
+    pub fn from_sorted_iter_with_comparator(
+        iter: impl IntoIterator<Item = (K, V)>,
+        config: BTreeConfig,
+        comparator: C,
+    ) -> Result<Self, SortedIterError> {
+        let key_values = node::build::collect_sorted(iter, &comparator)?;
+        Ok(Self {
+            root: Node::build(key_values, &config).map(Arc::new),
+            config,
+            comparator,
+        })
+    }
+
+    /// gets the entry of key for in-place manipulation
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = BTree::<&'static str, i32>::new();
+    /// for word in ["a", "b", "a"] {
+    ///     *tree.entry(word).or_insert(0) += 1;
+    /// }
+    /// assert_eq!(*tree.get_by_key(&"a").unwrap(), 2);
+    /// assert_eq!(*tree.get_by_key(&"b").unwrap(), 1);
+    /// ```
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C> {
+        let position = match self.root.as_ref() {
+            Some(root) => root.key_position(&key, &self.comparator),
+            None => KeyPosition {
+                path: vec![],
+                idx: Err(0),
+                offset: 0,
+            },
+        };
+
+        match position.idx {
+            Ok(idx) => Entry::Occupied(OccupiedEntry {
+                tree: self,
+                path: position.path,
+                idx,
+            }),
+            Err(idx) => Entry::Vacant(VacantEntry {
+                tree: self,
+                key,
+                path: position.path,
+                idx,
+            }),
+        }
+    }
+
+    /// get mutable value by key, only nodes on the path to the key are copied
+    /// if shared with snapshots
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = BTree::<i32, i32>::new();
+    /// tree.insert(1, 10);
+    /// let snapshot = tree.snapshot();
+    /// *tree.get_mut(&1).unwrap() += 1;
+    /// assert_eq!(*tree.get_by_key(&1).unwrap(), 11);
+    /// assert_eq!(*snapshot.get_by_key(&1).unwrap(), 10);
+    /// ```
+    pub fn get_mut<Q: ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        // locate first, so a missing key copies nothing
+        let position = self.root.as_ref()?.key_position(key, &self.comparator);
+        let idx = position.idx.ok()?;
+        let root = Arc::make_mut(self.root.as_mut()?);
+        Some(&mut root.get_mut_at_path(&position.path, idx).1)
+    }
+
+    /// get mutable key, value by offset, only nodes on the path to the offset
+    /// are copied if shared with snapshots. Changing the key must keep the
+    /// tree ordered.
+    pub fn get_mut_by_offset(&mut self, offset: usize) -> Option<&mut (K, V)> {
+        if self.root.as_ref()?.count <= offset {
+            return None;
+        }
+        Arc::make_mut(self.root.as_mut()?).get_mut_by_offset(offset)
+    }
+}
+
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone, S: Summary<K, V>> BTree<K, V, C, S> {
+    /// create a new tree keeping summary S in every node, see `Summary`. S
+    /// is usually given by the type of the tree.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::{BTree, BTreeConfig, Measure, Measured, OrdComparator};
+    ///
+    /// struct Len;
+    /// impl Measure<i32, String> for Len {
+    ///     fn measure(_key: &i32, value: &String) -> usize {
+    ///         value.len()
+    ///     }
+    /// }
+    ///
+    /// let config = BTreeConfig { max_degree: 8 };
+    /// let mut tree: BTree<i32, String, OrdComparator, Measured<Len>> =
+    ///     BTree::new_with_summary(config, OrdComparator);
+    /// tree.insert(1, "ab".to_string());
+    /// tree.insert(2, "cde".to_string());
+    /// assert_eq!(tree.total_measure(), 5);
+    /// ```
+    pub fn new_with_summary(config: BTreeConfig, comparator: C) -> Self {
+        Self {
+            root: None,
+            config,
+            comparator,
+        }
+    }
+
+    fn default_config() -> BTreeConfig {
+        BTreeConfig {
+            max_degree: std::cmp::max(20, 4096 / std::mem::size_of::<(K, V)>().max(1)),
+        }
+    }
+
+    /// take a O(1) snapshot of the tree, same as `clone`
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = BTree::<i32, i32>::new();
+    /// tree.insert(1, 1);
+    /// let snapshot = tree.snapshot();
+    /// tree.insert(2, 2);
+    /// assert!(snapshot.get_by_key(&2).is_none());
+    /// assert_eq!(*tree.get_by_key(&2).unwrap(), 2);
+    /// ```
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    /// number of key values in tree, O(1)
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |root| root.count)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
     }
 
     /// insert key value into map
     pub fn insert(&mut self, key: K, value: V) -> bool {
-        let (new_root, is_new) = match self.root.as_mut() {
+        match self.root.as_mut() {
             Some(root) => {
                 let root = Arc::make_mut(root);
-                match root.insert(key, value, &self.config) {
-                    InsertResult::Splited {
-                        new_k_v,
-                        new_l,
-                        new_r,
-                    } => {
-                        // root node splitted, make a new node
-                        (
-                            Node::new_with_key_values(vec![new_k_v], vec![new_l, new_r]),
-                            true,
-                        )
-                    }
-                    InsertResult::NotSplited { is_new } => {
-                        return is_new;
-                    }
-                }
+                let insert_result = root.insert(key, value, &self.comparator, &self.config);
+                self.grow_root(insert_result)
             }
-            None => (Node::new_with_key_values(vec![(key, value)], vec![]), true),
-        };
-        self.root = Some(Arc::new(new_root));
-        is_new
+            None => {
+                self.root = Some(Arc::new(Node::new_with_key_values(
+                    vec![(key, value)],
+                    vec![],
+                )));
+                true
+            }
+        }
     }
 
     /// delete by key
-    pub fn delete_by_key(&mut self, key: &K) -> Option<(K, V)> {
+    pub fn delete_by_key<Q: ?Sized>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
         let root = Arc::make_mut(self.root.as_mut()?);
-        let delete_result = root.delete_by_key(key, &self.config);
+        let delete_result = root.delete_by_key(key, &self.comparator, &self.config);
+        self.shrink_root();
+        delete_result
+    }
 
-        if root.count == 0 {
-            self.root = None
-        } else if root.key_values.len() == 0 {
-            // if root node key_value is empty, promote its child as new root
-            self.root = Some(root.children.remove(0))
+    /// delete by offset, returns the deleted key value
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = BTree::<i32, ()>::new();
+    /// for i in 0..10 {
+    ///     tree.insert(i * 10, ());
+    /// }
+    /// assert_eq!(tree.delete_by_offset(5), Some((50, ())));
+    /// assert_eq!(tree.get_by_offset(5).unwrap().0, 60);
+    /// assert_eq!(tree.delete_by_offset(9), None);
+    /// ```
+    pub fn delete_by_offset(&mut self, offset: usize) -> Option<(K, V)> {
+        if self.root.as_ref()?.count <= offset {
+            return None;
         }
-
+        let root = Arc::make_mut(self.root.as_mut()?);
+        let delete_result = root.delete_by_offset(offset, &self.config);
+        self.shrink_root();
         delete_result
     }
 
-    /// get value by key
-    pub fn get_by_key(&self, key: &K) -> Option<&V> {
-        self.root.as_ref()?.get_by_key(key)
+    /// remove and return the key value with the smallest key
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut queue = [(3, "c"), (1, "a"), (2, "b")].into_iter().collect::<BTree<_, _>>();
+    /// assert_eq!(queue.pop_first(), Some((1, "a")));
+    /// assert_eq!(queue.pop_last(), Some((3, "c")));
+    /// assert_eq!(queue.len(), 1);
+    /// ```
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let root = Arc::make_mut(self.root.as_mut()?);
+        let key_value = root.take_left_most(&self.config);
+        self.shrink_root();
+        Some(key_value)
     }
 
-    /// get key, value by offset
+    /// remove and return the key value with the largest key
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let root = Arc::make_mut(self.root.as_mut()?);
+        let key_value = root.take_right_most(&self.config);
+        self.shrink_root();
+        Some(key_value)
+    }
+
+    /// keep only key values for which f returns true, f is called in key
+    /// order. Each node is rebuilt at most once, subtrees where nothing is
+    /// removed stay shared with snapshots.
     ///
     /// # Examples
     /// ```
     /// use imord2::BTree;
     ///
-    /// let mut tree = BTree::<&'static str, i32>::new();
-    /// tree.insert("a", 1);
-    /// tree.insert("b", 2);
-    /// assert_eq!(tree.get_by_offset(0).unwrap().0, "a");
-    /// assert_eq!(tree.get_by_offset(1).unwrap().0, "b");
+    /// let mut tree = (0..10).map(|i| (i, i * 10)).collect::<BTree<i32, i32>>();
+    /// tree.retain(|k, _| k % 3 == 0);
+    /// assert_eq!(tree.keys().copied().collect::<Vec<_>>(), vec![0, 3, 6, 9]);
     /// ```
-    pub fn get_by_offset(&self, offset: usize) -> Option<&(K, V)> {
-        self.root.as_ref()?.get_by_offset(offset)
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &V) -> bool) {
+        let root = self.take_subtree();
+        self.root = root
+            .and_then(|root| root.retain(&mut f, &self.config))
+            .map(|r| r.node);
     }
 
-    pub fn find_key_range(&self, pred: impl Fn(&K) -> PredicateResult) -> KeyRangeResult<K> {
-        match self.root.as_ref() {
-            Some(root) => root.find_key_range(&pred),
-            None => KeyRangeResult::None,
+    /// lazily remove and yield key values for which pred returns true, in key
+    /// order. Key values not reached stay in tree if the iterator is dropped
+    /// early. To remove many key values at once, `retain` is cheaper.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = (0..10).map(|i| (i, ())).collect::<BTree<i32, ()>>();
+    /// let evens = tree.extract_if(|k, _| k % 2 == 0).map(|(k, _)| k).collect::<Vec<_>>();
+    /// assert_eq!(evens, vec![0, 2, 4, 6, 8]);
+    /// assert_eq!(tree.keys().copied().collect::<Vec<_>>(), vec![1, 3, 5, 7, 9]);
+    /// ```
+    pub fn extract_if<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        pred: F,
+    ) -> ExtractIf<'_, K, V, C, F, S> {
+        ExtractIf {
+            tree: self,
+            offset: 0,
+            pred,
         }
     }
 
-    /// visit inner node in Pre order
-    pub fn visit(&self, visit_fn: &mut impl FnMut(&visit::VisitStack<K, V>)) -> Option<()> {
-        let root = self.root.as_ref()?;
-        visit::visit_node(root, visit_fn);
-        Some(())
+    /// split tree at key, self keeps keys less than key, returns the rest.
+    /// Subtrees not on the split path are shared by both trees.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = (0..10).map(|i| (i, ())).collect::<BTree<i32, ()>>();
+    /// let upper = tree.split_off(&6);
+    /// assert_eq!(tree.keys().copied().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5]);
+    /// assert_eq!(upper.keys().copied().collect::<Vec<_>>(), vec![6, 7, 8, 9]);
+    /// ```
+    pub fn split_off<Q: ?Sized>(&mut self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let root = match self.take_subtree() {
+            Some(root) => root,
+            None => return self.new_empty(),
+        };
+        let split_result = root.split(&mut |node| node.search(key, &self.comparator), &self.config);
+        self.split_apart(split_result)
     }
-}
-
-mod node;
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    /// split tree at offset, self keeps key values before offset, returns the
+    /// rest. Subtrees not on the split path are shared by both trees.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = (0..10).map(|i| (i * 10, ())).collect::<BTree<i32, ()>>();
+    /// let upper = tree.split_off_at(7);
+    /// assert_eq!(tree.iter().len(), 7);
+    /// assert_eq!(upper.keys().copied().collect::<Vec<_>>(), vec![70, 80, 90]);
+    /// ```
+    pub fn split_off_at(&mut self, offset: usize) -> Self {
+        let root = match self.take_subtree() {
+            Some(root) if offset < root.node.count => root,
+            root => {
+                self.root = root.map(|r| r.node);
+                return self.new_empty();
+            }
+        };
+        let split_result = root.split(&mut Node::locate_offset(offset), &self.config);
+        self.split_apart(split_result)
+    }
 
-    #[test]
-    fn test_tree_insert() {
-        let mut tree = BTree::<i32, i32>::new_with_config(BTreeConfig { max_degree: 4 });
-        let keys = (1..13i32).rev().collect::<Vec<_>>();
-        for i in keys.iter() {
-            let is_new = tree.insert(*i, i * 100);
-            // first time insert, is_new should always be true
-            assert!(is_new);
-            assert_eq!(*tree.get_by_key(i).unwrap(), i * 100);
+    /// remove key values with key in range, returns them as a new tree. The
+    /// tree is split at both ends of the range and the outer parts are joined
+    /// back, so it costs O(log n) regardless of the range size.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = (0..10).map(|i| (i, ())).collect::<BTree<i32, ()>>();
+    /// let removed = tree.remove_range(3..7);
+    /// assert_eq!(removed.keys().copied().collect::<Vec<_>>(), vec![3, 4, 5, 6]);
+    /// assert_eq!(tree.keys().copied().collect::<Vec<_>>(), vec![0, 1, 2, 7, 8, 9]);
+    /// ```
+    pub fn remove_range<Q: ?Sized, R: RangeBounds<Q>>(&mut self, range: R) -> Self
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let (start, end) = self.range_offsets(range);
+        if start >= end {
+            return self.new_empty();
         }
 
-        for i in keys.iter() {
-            let is_new = tree.insert(*i, i * 100);
-            // second time insert, is_new should always be false
-            assert!(!is_new);
-            assert_eq!(*tree.get_by_key(i).unwrap(), i * 100);
-        }
+        let mut right = self.split_off_at(end);
+        let removed = self.split_off_at(start);
+        let joined = Subtree::concat(self.take_subtree(), right.take_subtree(), &self.config);
+        self.root = joined.map(|j| j.node);
+        removed
+    }
 
-        for i in keys.iter() {
-            tree.delete_by_key(i);
-        }
+    /// remove key values with key in range, returns an iterator over them.
+    /// The range is removed in O(log n) when called, even if the iterator is
+    /// not consumed.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = (0..10).map(|i| (i, i * 10)).collect::<BTree<i32, i32>>();
+    /// let drained = tree.drain_range(..=2).collect::<Vec<_>>();
+    /// assert_eq!(drained, vec![(0, 0), (1, 10), (2, 20)]);
+    /// assert_eq!(tree.len(), 7);
+    /// ```
+    pub fn drain_range<Q: ?Sized, R: RangeBounds<Q>>(&mut self, range: R) -> IntoIter<K, V, C, S>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.remove_range(range).into_iter()
+    }
 
-        for i in keys.iter() {
-            let is_new = tree.insert(*i, i * 1000);
-            // insert after delete, is_new should be true
-            assert!(is_new);
-            assert_eq!(*tree.get_by_key(i).unwrap(), i * 1000);
+    /// concatenate two trees, all keys in left must be less than keys in
+    /// right. The lower tree is grafted onto the spine of the higher one in
+    /// O(log n), other nodes are shared. Config of left is kept, if right
+    /// has a different max_degree it is rebuilt under left's config first,
+    /// which takes O(n) of right.
+    ///
+    /// # Panics
+    /// Panics if keys of the two trees overlap.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let left = (0..5).map(|i| (i, ())).collect::<BTree<i32, ()>>();
+    /// let right = (5..10).map(|i| (i, ())).collect::<BTree<i32, ()>>();
+    /// let tree = BTree::join(left, right);
+    /// assert!(tree.keys().copied().eq(0..10));
+    /// ```
+    pub fn join(mut left: Self, right: Self) -> Self {
+        if let (Some(left_last), Some(right_first)) = (left.last(), right.first()) {
+            assert!(
+                left.comparator.cmp(&left_last.0, &right_first.0) == Ordering::Less,
+                "keys in left tree must be less than keys in right tree"
+            );
         }
+
+        let mut right = right.rebuild_with_config(left.config);
+        let joined = Subtree::concat(left.take_subtree(), right.take_subtree(), &left.config);
+        left.root = joined.map(|j| j.node);
+        left
     }
 
-    #[test]
-    fn test_tree_delete() {
-        let mut tree = BTree::<i32, u32>::new_with_config(BTreeConfig { max_degree: 4 });
-        let keys = (1..13i32).collect::<Vec<_>>();
-        for i in keys.iter() {
-            tree.insert(*i, (i * 100) as u32);
-        }
+    /// move all key values of other into self, leave other empty. If keys of
+    /// the two trees do not overlap, they are joined in O(log n), otherwise
+    /// other's key values are inserted one by one and win on same key.
+    /// Config of self is kept, same as `join`.
+    pub fn append(&mut self, other: &mut Self) {
+        let other = std::mem::replace(other, other.new_empty()).rebuild_with_config(self.config);
+        let (self_first, self_last) = match (self.first(), self.last()) {
+            (Some(first), Some(last)) => (&first.0, &last.0),
+            _ => {
+                self.root = other.root;
+                return;
+            }
+        };
 
-        for i in keys.iter() {
-            // second time insert, is_new should always be false
-            let is_new = tree.insert(*i, (i * 1000) as u32);
-            assert!(!is_new);
+        match (other.first(), other.last()) {
+            (Some(other_first), _)
+                if self.comparator.cmp(self_last, &other_first.0) == Ordering::Less =>
+            {
+                let left = std::mem::replace(self, self.new_empty());
+                *self = Self::join(left, other);
+            }
+            (_, Some(other_last))
+                if self.comparator.cmp(&other_last.0, self_first) == Ordering::Less =>
+            {
+                let right = std::mem::replace(self, self.new_empty());
+                *self = Self::join(other, right);
+            }
+            _ => {
+                for (key, value) in other.iter() {
+                    self.insert(key.clone(), value.clone());
+                }
+            }
         }
+    }
 
-        dbg!(&tree);
+    /// key values in either tree, for key in both trees, value is
+    /// resolve(key, self_value, other_value). Subtrees shared by both trees
+    /// are reused without visiting, resolve is not called for them.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let a = [(1, 1), (2, 2)].into_iter().collect::<BTree<i32, i32>>();
+    /// let b = [(2, 20), (3, 30)].into_iter().collect::<BTree<i32, i32>>();
+    /// let union = a.union(&b, |_k, a, b| a + b);
+    /// assert_eq!(union.iter().copied().collect::<Vec<_>>(), vec![(1, 1), (2, 22), (3, 30)]);
+    /// ```
+    pub fn union(&self, other: &Self, mut resolve: impl FnMut(&K, &V, &V) -> V) -> Self {
+        self.set_op(other, SetOp::Union, &mut resolve)
+    }
 
-        let mut key_values = vec![];
-        for i in keys.iter() {
-            key_values.push(tree.delete_by_key(i).unwrap());
-            dbg!(&tree);
+    /// key values of self whose key is also in other
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.set_op(other, SetOp::Intersection, &mut |_, v, _| v.clone())
+    }
+
+    /// key values of self whose key is not in other
+    pub fn difference(&self, other: &Self) -> Self {
+        self.set_op(other, SetOp::Difference, &mut |_, v, _| v.clone())
+    }
+
+    /// key values whose key is in exactly one of the trees
+    pub fn symmetric_difference(&self, other: &Self) -> Self {
+        self.set_op(other, SetOp::SymmetricDifference, &mut |_, v, _| v.clone())
+    }
+
+    /// changes from self to new in key order. Subtrees shared by both trees
+    /// are skipped, so cost grows with the size of change rather than the
+    /// size of tree.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::{BTree, DiffItem};
+    ///
+    /// let old = (0..100).map(|i| (i, i)).collect::<BTree<i32, i32>>();
+    /// let mut new = old.clone();
+    /// new.insert(5, 50);
+    /// new.insert(200, 200);
+    /// new.delete_by_key(&7);
+    /// assert_eq!(
+    ///     old.diff(&new).collect::<Vec<_>>(),
+    ///     vec![
+    ///         DiffItem::Changed(&5, &5, &50),
+    ///         DiffItem::Removed(&7, &7),
+    ///         DiffItem::Added(&200, &200),
+    ///     ]
+    /// );
+    /// ```
+    pub fn diff<'a>(&'a self, new: &'a Self) -> Diff<'a, K, V, C, S>
+    where
+        V: PartialEq,
+    {
+        Diff::new(self.root.as_ref(), new.root.as_ref(), &self.comparator)
+    }
+
+    /// three way merge of two versions forked from base. Changes made by
+    /// theirs are applied on ours, keys changed by both sides differently are
+    /// passed to resolve, which returns the merged value or None to remove
+    /// the key. Subtrees unchanged by either side are skipped by diff, and
+    /// stay shared with ours.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let base = (0..10).map(|i| (i, i)).collect::<BTree<i32, i32>>();
+    /// let mut ours = base.clone();
+    /// let mut theirs = base.clone();
+    /// ours.insert(1, 100);
+    /// ours.insert(5, 500);
+    /// theirs.delete_by_key(&2);
+    /// theirs.insert(5, 5000);
+    ///
+    /// let merged = BTree::merge3(&base, &ours, &theirs, |conflict| {
+    ///     assert_eq!(*conflict.key, 5);
+    ///     Some(conflict.ours.unwrap() + conflict.theirs.unwrap())
+    /// });
+    /// assert_eq!(*merged.get_by_key(&1).unwrap(), 100);
+    /// assert!(merged.get_by_key(&2).is_none());
+    /// assert_eq!(*merged.get_by_key(&5).unwrap(), 5500);
+    /// ```
+    pub fn merge3(
+        base: &Self,
+        ours: &Self,
+        theirs: &Self,
+        mut resolve: impl FnMut(MergeConflict<'_, K, V>) -> Option<V>,
+    ) -> Self
+    where
+        V: PartialEq,
+    {
+        let mut merged = ours.clone();
+        let mut ours_diff = base.diff(ours).peekable();
+
+        for theirs_item in base.diff(theirs) {
+            let key = theirs_item.key();
+
+            // skip changes only made by ours
+            let cmp = |item: &DiffItem<'_, K, V>| base.comparator.cmp(item.key(), key);
+            while ours_diff.next_if(|item| cmp(item).is_lt()).is_some() {}
+
+            let new_value = match ours_diff.next_if(|item| cmp(item).is_eq()) {
+                None => theirs_item.new_value().cloned(),
+                Some(ours_item) if ours_item.new_value() == theirs_item.new_value() => continue,
+                Some(ours_item) => resolve(MergeConflict {
+                    key,
+                    base: theirs_item.old_value(),
+                    ours: ours_item.new_value(),
+                    theirs: theirs_item.new_value(),
+                }),
+            };
+
+            match new_value {
+                Some(value) => {
+                    merged.insert(key.clone(), value);
+                }
+                None => {
+                    merged.delete_by_key(key);
+                }
+            }
         }
 
-        assert_eq!(key_values.len(), keys.len());
+        merged
+    }
+
+    /// offsets of the first key in range and the one after the last
+    fn range_offsets<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> (usize, usize)
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let root = match self.root.as_deref() {
+            Some(root) => root,
+            None => return (0, 0),
+        };
+
+        let rank = |key: &Q| root.rank(key, &self.comparator);
+        let start = match range.start_bound() {
+            Bound::Included(key) => rank(key).unwrap_or_else(|offset| offset),
+            Bound::Excluded(key) => rank(key).map_or_else(|offset| offset, |o| o + 1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => rank(key).map_or_else(|offset| offset, |o| o + 1),
+            Bound::Excluded(key) => rank(key).unwrap_or_else(|offset| offset),
+            Bound::Unbounded => root.count,
+        };
+        (start, end)
+    }
+
+    /// offset of the first key above bound, count of tree if none
+    fn lower_bound_offset<Q: ?Sized>(&self, bound: Bound<&Q>) -> usize
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        match bound {
+            Bound::Included(key) => self.rank(key).unwrap_or_else(|offset| offset),
+            Bound::Excluded(key) => self.rank(key).map_or_else(|offset| offset, |o| o + 1),
+            Bound::Unbounded => 0,
+        }
+    }
+
+    /// offset of the last key below bound, count of tree if none
+    fn upper_bound_offset<Q: ?Sized>(&self, bound: Bound<&Q>) -> usize
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let end = match bound {
+            Bound::Included(key) => self.rank(key).map_or_else(|offset| offset, |o| o + 1),
+            Bound::Excluded(key) => self.rank(key).unwrap_or_else(|offset| offset),
+            Bound::Unbounded => self.len(),
+        };
+        end.checked_sub(1).unwrap_or(self.len())
+    }
+
+    /// offset range clamped to tree size
+    fn offset_range(&self, range: impl RangeBounds<usize>) -> (usize, usize) {
+        let count = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(offset) => *offset,
+            Bound::Excluded(offset) => offset.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(offset) => offset.saturating_add(1),
+            Bound::Excluded(offset) => *offset,
+            Bound::Unbounded => count,
+        };
+        (start.min(count), end.min(count))
+    }
+
+    /// summary of key values in [start, end) offset range
+    fn fold(&self, start: usize, end: usize) -> S {
+        match self.root.as_ref() {
+            Some(root) if start < end => root.fold_offsets(start, end),
+            _ => S::empty(),
+        }
+    }
+
+    /// empty tree with same config and comparator
+    fn new_empty(&self) -> Self {
+        Self::new_with_summary(self.config, self.comparator.clone())
+    }
+
+    fn set_op(&self, other: &Self, op: SetOp, resolve: &mut impl FnMut(&K, &V, &V) -> V) -> Self {
+        let result = Subtree::set_op(
+            self.clone().take_subtree(),
+            other.clone().take_subtree(),
+            op,
+            resolve,
+            &self.comparator,
+            &self.config,
+        );
+        Self {
+            root: result.map(|r| r.node),
+            config: self.config,
+            comparator: self.comparator.clone(),
+        }
+    }
+
+    /// same tree with nodes rebuilt under config, in O(n) if max_degree
+    /// differs, so its subtrees can be put together with another tree's
+    fn rebuild_with_config(mut self, config: BTreeConfig) -> Self {
+        if self.config.max_degree != config.max_degree {
+            let key_values = self.iter().cloned().collect();
+            self.root = Node::build(key_values, &config).map(Arc::new);
+            self.config = config;
+        }
+        self
+    }
+
+    /// take root out as a subtree, leave self empty
+    fn take_subtree(&mut self) -> Option<Subtree<K, V, S>> {
+        let node = self.root.take()?;
+        let height = node.height();
+        Some(Subtree { node, height })
+    }
+
+    /// keep left part in self, returns matched and right part as a new tree
+    fn split_apart(&mut self, split_result: SplitResult<K, V, S>) -> Self {
+        let SplitResult {
+            left,
+            matched,
+            right,
+        } = split_result;
+
+        let right = match matched {
+            Some(key_value) => Some(Subtree::join(None, key_value, right, &self.config)),
+            None => right,
+        };
+
+        self.root = left.map(|l| l.node);
+        Self {
+            root: right.map(|r| r.node),
+            config: self.config,
+            comparator: self.comparator.clone(),
+        }
+    }
+
+    /// make a new root if root splitted, returns whether a key is added
+    fn grow_root(&mut self, insert_result: InsertResult<K, V, S>) -> bool {
+        match insert_result {
+            InsertResult::Splited {
+                new_k_v,
+                new_l,
+                new_r,
+            } => {
+                // root node splitted, make a new node
+                self.root = Some(Arc::new(Node::new_with_key_values(
+                    vec![new_k_v],
+                    vec![new_l, new_r],
+                )));
+                true
+            }
+            InsertResult::NotSplited { is_new } => is_new,
+        }
+    }
+
+    /// drop root if tree becomes empty, or promote its only child after a
+    /// deletion
+    fn shrink_root(&mut self) {
+        let root = match self.root.as_mut() {
+            Some(root) => root,
+            None => return,
+        };
+
+        if root.count == 0 {
+            self.root = None
+        } else if root.key_values.is_empty() {
+            // if root node key_value is empty, promote its child as new root
+            let child = Arc::make_mut(root).children.remove(0);
+            self.root = Some(child)
+        }
+    }
+
+    /// get value by key
+    pub fn get_by_key<Q: ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.root.as_ref()?.get_by_key(key, &self.comparator)
+    }
+
+    /// get key, value by offset
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = BTree::<&'static str, i32>::new();
+    /// tree.insert("a", 1);
+    /// tree.insert("b", 2);
+    /// assert_eq!(tree.get_by_offset(0).unwrap().0, "a");
+    /// assert_eq!(tree.get_by_offset(1).unwrap().0, "b");
+    /// ```
+    pub fn get_by_offset(&self, offset: usize) -> Option<&(K, V)> {
+        self.root.as_ref()?.get_by_offset(offset)
+    }
+
+    /// key value with the smallest key
+    pub fn first(&self) -> Option<&(K, V)> {
+        self.get_by_offset(0)
+    }
+
+    /// key value with the largest key
+    pub fn last(&self) -> Option<&(K, V)> {
+        self.get_by_offset(self.len().checked_sub(1)?)
+    }
+
+    /// offset of key, follows `binary_search` conventions: `Ok(offset)` if key
+    /// exists, otherwise `Err(offset)` where the key would be inserted
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = BTree::<i32, ()>::new();
+    /// tree.insert(10, ());
+    /// tree.insert(20, ());
+    /// assert_eq!(tree.rank(&20), Ok(1));
+    /// assert_eq!(tree.rank(&15), Err(1));
+    /// assert_eq!(tree.rank(&30), Err(2));
+    /// ```
+    pub fn rank<Q: ?Sized>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        match self.root.as_ref() {
+            Some(root) => root.rank(key, &self.comparator),
+            None => Err(0),
+        }
+    }
+
+    pub fn find_key_range(&self, pred: impl Fn(&K) -> PredicateResult) -> KeyRangeResult<'_, K> {
+        match self.root.as_ref() {
+            Some(root) => root.find_key_range(&pred),
+            None => KeyRangeResult::None,
+        }
+    }
+
+    /// cursor at offset, at ghost if offset is out of tree
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let tree = (0..10).map(|i| (i * 10, ())).collect::<BTree<i32, ()>>();
+    /// let mut cursor = tree.cursor_at_offset(3);
+    /// assert_eq!(cursor.key(), Some(&30));
+    /// cursor.move_next();
+    /// assert_eq!(cursor.offset(), Some(4));
+    /// assert_eq!(cursor.peek_prev(), Some(&(30, ())));
+    /// ```
+    pub fn cursor_at_offset(&self, offset: usize) -> Cursor<'_, K, V, S> {
+        Cursor::new(self.root.as_deref(), offset)
+    }
+
+    /// cursor at the first key value above bound, at ghost if none
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    /// use std::ops::Bound;
+    ///
+    /// let tree = (0..10).map(|i| (i * 10, ())).collect::<BTree<i32, ()>>();
+    /// assert_eq!(tree.lower_bound(Bound::Included(&30)).key(), Some(&30));
+    /// assert_eq!(tree.lower_bound(Bound::Excluded(&30)).key(), Some(&40));
+    /// assert_eq!(tree.upper_bound(Bound::Excluded(&30)).key(), Some(&20));
+    /// assert_eq!(tree.upper_bound(Bound::Included(&35)).key(), Some(&30));
+    /// assert_eq!(tree.lower_bound(Bound::Excluded(&90)).key(), None);
+    /// ```
+    pub fn lower_bound<Q: ?Sized>(&self, bound: Bound<&Q>) -> Cursor<'_, K, V, S>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.cursor_at_offset(self.lower_bound_offset(bound))
+    }
+
+    /// cursor at the last key value below bound, at ghost if none
+    pub fn upper_bound<Q: ?Sized>(&self, bound: Bound<&Q>) -> Cursor<'_, K, V, S>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.cursor_at_offset(self.upper_bound_offset(bound))
+    }
+
+    /// mutable cursor at offset, at ghost if offset is out of tree
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = (0..5).map(|i| (i * 10, ())).collect::<BTree<i32, ()>>();
+    /// let mut cursor = tree.cursor_mut_at_offset(2);
+    /// cursor.insert_before(15, ());
+    /// cursor.insert_after(25, ());
+    /// assert_eq!(cursor.remove_current(), Some((20, ())));
+    /// assert_eq!(cursor.key(), Some(&25));
+    /// assert_eq!(tree.keys().copied().collect::<Vec<_>>(), vec![0, 10, 15, 25, 30, 40]);
+    /// ```
+    pub fn cursor_mut_at_offset(&mut self, offset: usize) -> CursorMut<'_, K, V, C, S> {
+        CursorMut::new(self, offset)
+    }
+
+    /// mutable cursor at the first key value above bound, at ghost if none
+    pub fn lower_bound_mut<Q: ?Sized>(&mut self, bound: Bound<&Q>) -> CursorMut<'_, K, V, C, S>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let offset = self.lower_bound_offset(bound);
+        CursorMut::new(self, offset)
+    }
+
+    /// mutable cursor at the last key value below bound, at ghost if none
+    pub fn upper_bound_mut<Q: ?Sized>(&mut self, bound: Bound<&Q>) -> CursorMut<'_, K, V, C, S>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let offset = self.upper_bound_offset(bound);
+        CursorMut::new(self, offset)
+    }
+
+    /// iterate key values in key order
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = BTree::<i32, i32>::new();
+    /// tree.insert(2, 20);
+    /// tree.insert(1, 10);
+    /// tree.insert(3, 30);
+    /// assert_eq!(tree.iter().len(), 3);
+    /// assert_eq!(tree.iter().collect::<Vec<_>>(), vec![&(1, 10), &(2, 20), &(3, 30)]);
+    /// assert_eq!(tree.iter().next_back(), Some(&(3, 30)));
+    /// ```
+    pub fn iter(&self) -> Iter<'_, K, V, S> {
+        let root = self.root.as_deref();
+        Iter::new(root, 0, root.map_or(0, |r| r.count))
+    }
+
+    /// iterate keys in key order
+    pub fn keys(&self) -> Keys<'_, K, V, S> {
+        Keys { inner: self.iter() }
+    }
+
+    /// iterate values in key order
+    pub fn values(&self) -> Values<'_, K, V, S> {
+        Values { inner: self.iter() }
+    }
+
+    /// iterate key values with key in range
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = BTree::<i32, ()>::new();
+    /// for i in 0..10 {
+    ///     tree.insert(i, ());
+    /// }
+    /// let range = tree.range(3..7);
+    /// assert_eq!(range.len(), 4);
+    /// assert_eq!(range.rev().map(|(k, _)| *k).collect::<Vec<_>>(), vec![6, 5, 4, 3]);
+    /// ```
+    pub fn range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> Iter<'_, K, V, S>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let (start, end) = self.range_offsets(range);
+        Iter::new(self.root.as_deref(), start, end)
+    }
+
+    /// iterate key values in offset range, the range is clamped to tree size
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTree;
+    ///
+    /// let mut tree = BTree::<i32, ()>::new();
+    /// for i in 0..100 {
+    ///     tree.insert(i * 10, ());
+    /// }
+    /// let page = tree.iter_offsets(20..23);
+    /// assert_eq!(page.map(|(k, _)| *k).collect::<Vec<_>>(), vec![200, 210, 220]);
+    /// assert_eq!(tree.iter_offsets(98..).len(), 2);
+    /// ```
+    pub fn iter_offsets(&self, range: impl RangeBounds<usize>) -> Iter<'_, K, V, S> {
+        let (start, end) = self.offset_range(range);
+        Iter::new(self.root.as_deref(), start, end)
+    }
+
+    /// summary of all key values, O(1)
+    pub fn summary(&self) -> S {
+        self.root
+            .as_ref()
+            .map_or_else(S::empty, |root| root.summary.clone())
+    }
+
+    /// summary of key values with key in range. Subtrees inside the range
+    /// are not visited, their summaries are combined as is, so it costs
+    /// O(log n) regardless of the range size.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::{BTree, BTreeConfig, Field, Max, OrdComparator, Sum};
+    ///
+    /// struct Price;
+    /// impl Field<u32, i64> for Price {
+    ///     type Value = i64;
+    ///     fn get(_time: &u32, price: &i64) -> i64 {
+    ///         *price
+    ///     }
+    /// }
+    ///
+    /// let config = BTreeConfig { max_degree: 8 };
+    /// let mut prices: BTree<u32, i64, OrdComparator, (Sum<i64, Price>, Max<i64, Price>)> =
+    ///     BTree::new_with_summary(config, OrdComparator);
+    /// for (time, price) in [(1, 10), (2, 30), (3, 20), (4, 50)] {
+    ///     prices.insert(time, price);
+    /// }
+    ///
+    /// let (sum, max) = prices.fold_range(2..=3);
+    /// assert_eq!((*sum.value(), max.value()), (50, Some(&30)));
+    /// let (sum, max) = prices.fold_offsets(..);
+    /// assert_eq!((*sum.value(), max.value()), (110, Some(&50)));
+    /// ```
+    pub fn fold_range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> S
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        let (start, end) = self.range_offsets(range);
+        self.fold(start, end)
+    }
+
+    /// summary of key values in offset range in O(log n), the range is
+    /// clamped to tree size
+    pub fn fold_offsets(&self, range: impl RangeBounds<usize>) -> S {
+        let (start, end) = self.offset_range(range);
+        self.fold(start, end)
+    }
+
+    /// visit inner node in Pre order
+    pub fn visit(&self, visit_fn: &mut impl FnMut(&visit::VisitStack<K, V>)) -> Option<()> {
+        let root = self.root.as_ref()?;
+        visit::visit_node(root, visit_fn);
+        Some(())
+    }
+}
+
+/// trees whose key values are weighted by a `Measure`
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone, M: Measure<K, V>> BTree<K, V, C, Measured<M>> {
+    /// sum of measures of all key values, O(1)
+    pub fn total_measure(&self) -> usize {
+        self.root.as_ref().map_or(0, |root| root.summary.total())
+    }
+
+    /// the key value covering cumulative position x, together with the
+    /// position where it starts. A key value covers
+    /// [start, start + measure), so ones measuring 0 are never returned.
+    /// None if x is not less than `total_measure`. Costs O(log n), like
+    /// `get_by_offset` which is the case of measuring every key value as 1.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::{BTree, BTreeConfig, Measure, Measured, OrdComparator};
+    ///
+    /// // messages of a log segment keyed by sequence number
+    /// struct ByteLen;
+    /// impl Measure<u64, Vec<u8>> for ByteLen {
+    ///     fn measure(_seq: &u64, message: &Vec<u8>) -> usize {
+    ///         message.len()
+    ///     }
+    /// }
+    ///
+    /// let config = BTreeConfig { max_degree: 8 };
+    /// let mut log: BTree<u64, Vec<u8>, OrdComparator, Measured<ByteLen>> =
+    ///     BTree::new_with_summary(config, OrdComparator);
+    /// log.insert(1, vec![0; 10]);
+    /// log.insert(2, vec![0; 5]);
+    /// log.insert(3, vec![0; 20]);
+    ///
+    /// let (start, (seq, _)) = log.get_by_measure(12).unwrap();
+    /// assert_eq!((start, *seq), (10, 2));
+    /// assert_eq!(log.get_by_measure(15).unwrap().1 .0, 3);
+    /// assert!(log.get_by_measure(35).is_none());
+    /// ```
+    pub fn get_by_measure(&self, x: usize) -> Option<(usize, &(K, V))> {
+        let root = self.root.as_ref()?;
+        let (_, before, key_value) = root.locate_by_measure(x, |s| s.total())?;
+        Some((before.total(), key_value))
+    }
+}
+
+/// Sorts key values then builds tree bottom up, for duplicated keys the last
+/// value wins.
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone + Default, S: Summary<K, V>> FromIterator<(K, V)>
+    for BTree<K, V, C, S>
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let comparator = C::default();
+        let mut key_values = iter.into_iter().collect::<Vec<_>>();
+        // stable sort keeps duplicated keys in input order
+        key_values.sort_by(|(a, _), (b, _)| comparator.cmp(a, b));
+
+        let mut deduped: Vec<(K, V)> = Vec::with_capacity(key_values.len());
+        for (key, value) in key_values {
+            match deduped.last_mut() {
+                Some(last) if comparator.cmp(&last.0, &key) == Ordering::Equal => {
+                    *last = (key, value)
+                }
+                _ => deduped.push((key, value)),
+            }
+        }
+
+        let config = Self::default_config();
+        Self {
+            root: Node::build(deduped, &config).map(Arc::new),
+            config,
+            comparator,
+        }
+    }
+}
+
+impl<K: Clone, V: Clone, C: Comparator<K> + Clone, S: Summary<K, V>> IntoIterator
+    for BTree<K, V, C, S>
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, C, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { tree: self }
+    }
+}
+
+impl<'a, K: Clone, V: Clone, C: Comparator<K> + Clone, S: Summary<K, V>> IntoIterator
+    for &'a BTree<K, V, C, S>
+{
+    type Item = &'a (K, V);
+    type IntoIter = Iter<'a, K, V, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+mod comparator;
+mod cursor;
+mod entry;
+mod extract_if;
+mod into_iter;
+mod node;
+mod rope;
+mod sequence;
+mod set;
+mod summary;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// count nodes in tree which are not shared with any of bases
+    fn new_nodes<K, V>(tree: &BTree<K, V>, bases: &[&BTree<K, V>]) -> usize {
+        fn collect<K, V>(node: &Arc<Node<K, V>>, nodes: &mut Vec<*const Node<K, V>>) {
+            nodes.push(Arc::as_ptr(node));
+            for child in node.children.iter() {
+                collect(child, nodes);
+            }
+        }
+
+        let mut base_nodes = vec![];
+        for root in bases.iter().filter_map(|base| base.root.as_ref()) {
+            collect(root, &mut base_nodes);
+        }
+
+        let mut count = 0;
+        let mut stack = tree.root.iter().collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            if !base_nodes.contains(&Arc::as_ptr(node)) {
+                count += 1;
+                stack.extend(node.children.iter());
+            }
+        }
+        count
+    }
+
+    /// run f for each max_degree with the same deterministic pseudo random
+    /// source, next(bound) returns a number in [0, bound)
+    fn for_each_degree_random(
+        degrees: &[usize],
+        mut f: impl FnMut(BTreeConfig, &mut dyn FnMut(usize) -> usize),
+    ) {
+        for &max_degree in degrees {
+            let mut state = 12345u64;
+            let mut next = |bound: usize| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 33) as usize % bound.max(1)
+            };
+            f(BTreeConfig { max_degree }, &mut next);
+        }
+    }
+
+    #[test]
+    fn test_tree_insert() {
+        let mut tree = BTree::<i32, i32>::new_with_config(BTreeConfig { max_degree: 4 });
+        let keys = (1..13i32).rev().collect::<Vec<_>>();
+        for i in keys.iter() {
+            let is_new = tree.insert(*i, i * 100);
+            // first time insert, is_new should always be true
+            assert!(is_new);
+            assert_eq!(*tree.get_by_key(i).unwrap(), i * 100);
+        }
+
+        for i in keys.iter() {
+            let is_new = tree.insert(*i, i * 100);
+            // second time insert, is_new should always be false
+            assert!(!is_new);
+            assert_eq!(*tree.get_by_key(i).unwrap(), i * 100);
+        }
+
+        for i in keys.iter() {
+            tree.delete_by_key(i);
+        }
+
+        for i in keys.iter() {
+            let is_new = tree.insert(*i, i * 1000);
+            // insert after delete, is_new should be true
+            assert!(is_new);
+            assert_eq!(*tree.get_by_key(i).unwrap(), i * 1000);
+        }
+    }
+
+    #[test]
+    fn test_tree_delete() {
+        let mut tree = BTree::<i32, u32>::new_with_config(BTreeConfig { max_degree: 4 });
+        let keys = (1..13i32).collect::<Vec<_>>();
+        for i in keys.iter() {
+            tree.insert(*i, (i * 100) as u32);
+        }
+
+        for i in keys.iter() {
+            // second time insert, is_new should always be false
+            let is_new = tree.insert(*i, (i * 1000) as u32);
+            assert!(!is_new);
+        }
+
+        dbg!(&tree);
+
+        let mut key_values = vec![];
+        for i in keys.iter() {
+            key_values.push(tree.delete_by_key(i).unwrap());
+            dbg!(&tree);
+        }
+
+        assert_eq!(key_values.len(), keys.len());
+    }
+
+    #[test]
+    fn test_tree_iter() {
+        let mut tree = BTree::<i32, i32>::new_with_config(BTreeConfig { max_degree: 4 });
+        assert_eq!(tree.iter().next(), None);
+
+        for i in (0..200).rev() {
+            tree.insert(i, i * 10);
+        }
+        for i in (0..200).step_by(3) {
+            tree.delete_by_key(&i);
+        }
+
+        let expected = (0..200).filter(|i| i % 3 != 0).collect::<Vec<_>>();
+        assert_eq!(tree.iter().len(), expected.len());
+        assert_eq!(tree.keys().copied().collect::<Vec<_>>(), expected);
+        assert_eq!(
+            tree.values().rev().copied().collect::<Vec<_>>(),
+            expected.iter().rev().map(|i| i * 10).collect::<Vec<_>>()
+        );
+        assert_eq!((&tree).into_iter().count(), expected.len());
+    }
+
+    #[test]
+    fn test_tree_range() {
+        let mut tree = BTree::<i32, i32>::new_with_config(BTreeConfig { max_degree: 5 });
+        assert_eq!(tree.range(..).len(), 0);
+
+        // even keys only, so bounds hit both existing and missing keys
+        for i in 0..100 {
+            tree.insert(i * 2, i);
+        }
+
+        let bounds = [
+            Bound::Unbounded,
+            Bound::Included(-1),
+            Bound::Included(0),
+            Bound::Excluded(0),
+            Bound::Included(51),
+            Bound::Excluded(52),
+            Bound::Included(198),
+            Bound::Excluded(198),
+            Bound::Excluded(300),
+        ];
+        for start in bounds {
+            for end in bounds {
+                let expected = (0..100)
+                    .map(|i| i * 2)
+                    .filter(|k| (start, end).contains(k))
+                    .collect::<Vec<_>>();
+                let range = tree.range((start, end));
+                assert_eq!(range.len(), expected.len());
+                assert_eq!(range.map(|(k, _)| *k).collect::<Vec<_>>(), expected);
+                assert_eq!(
+                    tree.range((start, end))
+                        .rev()
+                        .map(|(k, _)| *k)
+                        .collect::<Vec<_>>(),
+                    expected.into_iter().rev().collect::<Vec<_>>()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_tree_iter_offsets() {
+        let mut tree = BTree::<i32, i32>::new_with_config(BTreeConfig { max_degree: 4 });
+        for i in 0..60 {
+            tree.insert(i, i);
+        }
+
+        for start in 0..=65 {
+            for end in start..=65 {
+                let expected = (start.min(60)..end.min(60)).collect::<Vec<_>>();
+                let page = tree.iter_offsets(start as usize..end as usize);
+                assert_eq!(page.len(), expected.len());
+                assert_eq!(page.map(|(k, _)| *k).collect::<Vec<_>>(), expected);
+                assert_eq!(
+                    tree.iter_offsets(start as usize..end as usize)
+                        .rev()
+                        .map(|(k, _)| *k)
+                        .collect::<Vec<_>>(),
+                    expected.into_iter().rev().collect::<Vec<_>>()
+                );
+            }
+        }
+
+        assert_eq!(tree.iter_offsets(..=0).len(), 1);
+        assert_eq!(tree.iter_offsets(..).len(), 60);
+        assert_eq!(
+            tree.iter_offsets((Bound::Excluded(58), Bound::Unbounded))
+                .next(),
+            Some(&(59, 59))
+        );
+    }
+
+    #[test]
+    fn test_tree_rank() {
+        let mut tree = BTree::<i32, i32>::new_with_config(BTreeConfig { max_degree: 4 });
+        assert_eq!(tree.rank(&0), Err(0));
+
+        let keys = (0..150).map(|i| i * 2).collect::<Vec<_>>();
+        for k in keys.iter().rev() {
+            tree.insert(*k, *k);
+        }
+
+        for k in -1..301 {
+            assert_eq!(tree.rank(&k), keys.binary_search(&k));
+            if let Ok(offset) = tree.rank(&k) {
+                assert_eq!(tree.get_by_offset(offset).unwrap().0, k);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tree_invariants() {
+        for max_degree in 3..8 {
+            let config = BTreeConfig { max_degree };
+            let mut tree = BTree::<i32, i32>::new_with_config(config);
+            // insert and delete in a scrambled order
+            let keys = (0..300).map(|i| (i * 37) % 300).collect::<Vec<_>>();
+            for k in keys.iter() {
+                tree.insert(*k, *k);
+                tree.root.as_ref().unwrap().check_invariants(&config);
+            }
+            for k in keys.iter().rev().step_by(2) {
+                tree.delete_by_key(k);
+                tree.root.as_ref().unwrap().check_invariants(&config);
+            }
+            for k in keys.iter() {
+                tree.delete_by_key(k);
+                if let Some(root) = tree.root.as_ref() {
+                    root.check_invariants(&config);
+                }
+            }
+            assert!(tree.root.is_none());
+        }
+    }
+
+    #[test]
+    fn test_tree_entry() {
+        let config = BTreeConfig { max_degree: 4 };
+        let mut tree = BTree::<i32, i32>::new_with_config(config);
+
+        // vacant insert, splits happen along the way
+        for i in (0..100).rev() {
+            let value = tree.entry(i).or_insert(i);
+            assert_eq!(*value, i);
+            *value += 1000;
+            tree.root.as_ref().unwrap().check_invariants(&config);
+        }
+
+        let snapshot = tree.snapshot();
+
+        for i in 0..100 {
+            tree.entry(i).and_modify(|v| *v += 1).or_insert(0);
+            assert!(matches!(tree.entry(i), Entry::Occupied(_)));
+        }
+        assert!(tree.iter().all(|(k, v)| *v == k + 1001));
+        assert!(snapshot.iter().all(|(k, v)| *v == k + 1000));
+
+        for i in (0..100).step_by(2) {
+            match tree.entry(i) {
+                Entry::Occupied(entry) => assert_eq!(entry.remove_entry(), (i, i + 1001)),
+                Entry::Vacant(_) => unreachable!(),
+            }
+            tree.root.as_ref().unwrap().check_invariants(&config);
+        }
+        assert_eq!(tree.iter().len(), 50);
+        assert!(tree.keys().all(|k| k % 2 == 1));
+        assert_eq!(snapshot.iter().len(), 100);
+
+        match tree.entry(0) {
+            Entry::Vacant(entry) => assert_eq!(*entry.key(), 0),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert!(tree.get_by_key(&0).is_none());
+
+        // inserted slot is followed through splits at any position
+        for max_degree in [3, 4, 5, 8] {
+            let config = BTreeConfig { max_degree };
+            let mut tree = BTree::<i32, i32>::new_with_config(config);
+            for i in 0..500 {
+                let key = i * 37 % 500;
+                let value = tree.entry(key).or_insert(key);
+                assert_eq!(*value, key);
+                *value = -key;
+            }
+            tree.root.as_ref().unwrap().check_invariants(&config);
+            assert!(tree.iter().all(|(k, v)| *v == -k));
+        }
+    }
+
+    #[test]
+    fn test_tree_get_mut() {
+        let mut tree = BTree::<i32, i32>::new_with_config(BTreeConfig { max_degree: 4 });
+        for i in 0..100 {
+            tree.insert(i, i);
+        }
+        let snapshot = tree.snapshot();
+
+        // missing key or offset copies nothing
+        assert!(tree.get_mut(&1000).is_none());
+        assert!(tree.get_mut_by_offset(100).is_none());
+        assert!(Arc::ptr_eq(
+            tree.root.as_ref().unwrap(),
+            snapshot.root.as_ref().unwrap()
+        ));
+
+        *tree.get_mut(&10).unwrap() = -10;
+        tree.get_mut_by_offset(90).unwrap().1 = -90;
+        assert_eq!(*tree.get_by_key(&10).unwrap(), -10);
+        assert_eq!(*tree.get_by_key(&90).unwrap(), -90);
+        assert_eq!(*snapshot.get_by_key(&10).unwrap(), 10);
+        assert_eq!(*snapshot.get_by_key(&90).unwrap(), 90);
+
+        // nodes off the two paths are still shared
+        let root = tree.root.as_ref().unwrap();
+        let snapshot_root = snapshot.root.as_ref().unwrap();
+        assert!(root.children.len() > 2);
+        let shared = root
+            .children
+            .iter()
+            .zip(snapshot_root.children.iter())
+            .filter(|(a, b)| Arc::ptr_eq(a, b))
+            .count();
+        assert_eq!(shared, root.children.len() - 2);
+    }
+
+    #[test]
+    fn test_tree_delete_by_offset() {
+        for max_degree in 3..7 {
+            let config = BTreeConfig { max_degree };
+            let mut tree = BTree::<i32, i32>::new_with_config(config);
+            let mut expected = (0..200).collect::<Vec<_>>();
+            for i in expected.iter() {
+                tree.insert(*i, *i);
+            }
+            let snapshot = tree.snapshot();
+
+            assert!(tree.delete_by_offset(200).is_none());
+
+            // delete from front, back and middle
+            let mut n = 0;
+            while !expected.is_empty() {
+                let offset = (n * 7) % expected.len();
+                let key = expected.remove(offset);
+                assert_eq!(tree.delete_by_offset(offset), Some((key, key)));
+                if let Some(root) = tree.root.as_ref() {
+                    root.check_invariants(&config);
+                }
+                assert_eq!(tree.iter().len(), expected.len());
+                n += 1;
+            }
+
+            assert!(tree.root.is_none());
+            assert_eq!(snapshot.iter().len(), 200);
+        }
+    }
+
+    #[test]
+    fn test_tree_from_iter() {
+        let tree = [(3, "c"), (1, "a"), (2, "b"), (1, "aa")]
+            .into_iter()
+            .collect::<BTree<_, _>>();
+        assert_eq!(
+            tree.iter().copied().collect::<Vec<_>>(),
+            vec![(1, "aa"), (2, "b"), (3, "c")]
+        );
+
+        let config = BTreeConfig { max_degree: 4 };
+        let mut tree = BTree::from_sorted_iter((0..100).map(|i| (i, i)), config).unwrap();
+        tree.root.as_ref().unwrap().check_invariants(&config);
+        // bulk loaded tree works with later mutations
+        for i in (0..100).step_by(3) {
+            tree.delete_by_key(&i);
+            tree.insert(i + 1000, i);
+            tree.root.as_ref().unwrap().check_invariants(&config);
+        }
+        assert_eq!(tree.iter().len(), 100);
+
+        assert!(BTree::<i32, ()>::from_sorted_iter([], config)
+            .unwrap()
+            .root
+            .is_none());
+        assert_eq!(
+            BTree::from_sorted_iter([(1, ()), (2, ()), (2, ())], config).unwrap_err(),
+            SortedIterError::Duplicate { offset: 2 }
+        );
+    }
+
+    #[test]
+    fn test_tree_split_off() {
+        for max_degree in 3..7 {
+            let config = BTreeConfig { max_degree };
+            let n = 150;
+            let tree = BTree::from_sorted_iter((0..n).map(|i| (i * 2, i)), config).unwrap();
+
+            for key in -1..n * 2 + 1 {
+                let mut left = tree.clone();
+                let right = left.split_off(&key);
+                for part in [&left, &right] {
+                    if let Some(root) = part.root.as_ref() {
+                        root.check_invariants(&config);
+                    }
+                }
+                assert!(left.keys().all(|k| *k < key));
+                assert!(right.keys().all(|k| *k >= key));
+                assert_eq!(left.iter().len() + right.iter().len(), n as usize);
+            }
+
+            for offset in 0..n as usize + 1 {
+                let mut left = tree.clone();
+                let right = left.split_off_at(offset);
+                for part in [&left, &right] {
+                    if let Some(root) = part.root.as_ref() {
+                        root.check_invariants(&config);
+                    }
+                }
+                assert_eq!(left.iter().len(), offset);
+                assert!(right
+                    .iter()
+                    .map(|(_, v)| *v as usize)
+                    .eq(offset..n as usize));
+            }
+
+            // untouched subtrees are shared with the original, only nodes
+            // near the split path are new
+            let mut left = tree.clone();
+            let right = left.split_off_at(n as usize / 2);
+            let height = tree.root.as_ref().unwrap().height();
+            assert!(new_nodes(&left, &[&tree]) <= 2 * (height + 1));
+            assert!(new_nodes(&right, &[&tree]) <= 2 * (height + 1));
+        }
+    }
+
+    #[test]
+    fn test_tree_join() {
+        for max_degree in 3..7 {
+            let config = BTreeConfig { max_degree };
+            let keys = |range: std::ops::Range<i32>| range.map(|i| (i, i));
+
+            // trees of all size combinations, so heights differ a lot
+            for left_n in [0, 1, 2, 5, 30, 200] {
+                for right_n in [0, 1, 2, 5, 30, 200] {
+                    let left = BTree::from_sorted_iter(keys(0..left_n), config).unwrap();
+                    let right =
+                        BTree::from_sorted_iter(keys(left_n..left_n + right_n), config).unwrap();
+
+                    let tree = BTree::join(left.clone(), right.clone());
+                    if let Some(root) = tree.root.as_ref() {
+                        root.check_invariants(&config);
+                    }
+                    assert!(tree.keys().copied().eq(0..left_n + right_n));
+
+                    // only nodes on the grafted spine are new
+                    let height = tree.root.as_ref().map_or(0, |r| r.height());
+                    assert!(new_nodes(&tree, &[&left, &right]) <= 2 * (height + 1));
+                }
+            }
+
+            // split then join gives back the same key values
+            let tree = BTree::from_sorted_iter(keys(0..300), config).unwrap();
+            for offset in (0..300).step_by(7) {
+                let mut left = tree.clone();
+                let right = left.split_off_at(offset);
+                let joined = BTree::join(left, right);
+                joined.root.as_ref().unwrap().check_invariants(&config);
+                assert!(joined.keys().copied().eq(0..300));
+            }
+        }
+    }
+
+    #[test]
+    fn test_tree_append() {
+        let config = BTreeConfig { max_degree: 4 };
+        let mut tree = BTree::from_sorted_iter((10..20).map(|i| (i, 0)), config).unwrap();
+
+        let mut lower = BTree::from_sorted_iter((0..10).map(|i| (i, 1)), config).unwrap();
+        tree.append(&mut lower);
+        assert!(lower.iter().next().is_none());
+
+        let mut upper = BTree::from_sorted_iter((20..30).map(|i| (i, 2)), config).unwrap();
+        tree.append(&mut upper);
+
+        // overlapping keys, other wins
+        let mut overlap = BTree::from_sorted_iter((5..25).map(|i| (i, 3)), config).unwrap();
+        tree.append(&mut overlap);
+
+        tree.root.as_ref().unwrap().check_invariants(&config);
+        let expected = (0..30).map(|i| match i {
+            5..=24 => (i, 3),
+            0..=4 => (i, 1),
+            _ => (i, 2),
+        });
+        assert!(tree.iter().copied().eq(expected));
+    }
+
+    #[test]
+    fn test_tree_join_different_degree() {
+        let small = BTreeConfig { max_degree: 3 };
+        let large = BTreeConfig { max_degree: 16 };
+
+        // left's config is kept by join, the other side is rebuilt
+        for (left_config, right_config) in [(small, large), (large, small)] {
+            let left = BTree::from_sorted_iter((0..500).map(|i| (i, ())), left_config).unwrap();
+            let right = BTree::from_sorted_iter((500..600).map(|i| (i, ())), right_config).unwrap();
+            let joined = BTree::join(left, right);
+            assert_eq!(joined.config.max_degree, left_config.max_degree);
+            joined.root.as_ref().unwrap().check_invariants(&left_config);
+            assert!(joined.keys().copied().eq(0..600));
+        }
+
+        // self's config is kept by append, whichever way keys go
+        for range in [0..100, 900..1000, 450..550] {
+            let mut tree = BTree::from_sorted_iter((100..900).map(|i| (i, ())), small).unwrap();
+            let mut other = BTree::from_sorted_iter(range.clone().map(|i| (i, ())), large).unwrap();
+            tree.append(&mut other);
+            assert!(other.is_empty());
+            assert_eq!(tree.config.max_degree, small.max_degree);
+            tree.root.as_ref().unwrap().check_invariants(&small);
+            assert_eq!(
+                tree.len(),
+                800 + range.filter(|i| !(100..900).contains(i)).count()
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_tree_join_overlap() {
+        let left = (0..10).map(|i| (i, ())).collect::<BTree<i32, ()>>();
+        let right = (5..15).map(|i| (i, ())).collect::<BTree<i32, ()>>();
+        BTree::join(left, right);
+    }
+
+    #[test]
+    fn test_tree_set_algebra() {
+        use std::collections::BTreeMap;
+
+        for max_degree in [3, 4, 7] {
+            let config = BTreeConfig { max_degree };
+            let build = |keys: &mut dyn Iterator<Item = i32>, tag: i32| {
+                let mut tree = BTree::new_with_config(config);
+                let mut map = BTreeMap::new();
+                for k in keys {
+                    tree.insert(k, k * 10 + tag);
+                    map.insert(k, k * 10 + tag);
+                }
+                (tree, map)
+            };
+
+            let inputs = [
+                (0..0).collect::<Vec<_>>(),
+                (0..100).collect(),
+                (0..100).step_by(3).collect(),
+                (50..300).step_by(2).collect(),
+                (0..300).map(|i| (i * 37) % 300).collect(),
+            ];
+            for a_keys in inputs.iter() {
+                for b_keys in inputs.iter() {
+                    let (a, a_map) = build(&mut a_keys.iter().copied(), 1);
+                    let (b, b_map) = build(&mut b_keys.iter().copied(), 2);
+
+                    let union = a.union(&b, |_, x, y| x + y);
+                    let mut expected = a_map.clone();
+                    for (k, v) in b_map.iter() {
+                        *expected.entry(*k).or_insert(0) += v;
+                    }
+                    assert!(union.iter().map(|(k, v)| (k, v)).eq(expected.iter()));
+
+                    let intersection = a.intersection(&b);
+                    let expected = a_map.iter().filter(|(k, _)| b_map.contains_key(k));
+                    assert!(intersection.iter().map(|(k, v)| (k, v)).eq(expected));
+
+                    let difference = a.difference(&b);
+                    let expected = a_map.iter().filter(|(k, _)| !b_map.contains_key(k));
+                    assert!(difference.iter().map(|(k, v)| (k, v)).eq(expected));
+
+                    let symmetric_difference = a.symmetric_difference(&b);
+                    let mut expected = a_map.clone();
+                    for (k, v) in b_map.iter() {
+                        if expected.remove(k).is_none() {
+                            expected.insert(*k, *v);
+                        }
+                    }
+                    assert!(symmetric_difference
+                        .iter()
+                        .map(|(k, v)| (k, v))
+                        .eq(expected.iter()));
+
+                    for tree in [union, intersection, difference, symmetric_difference] {
+                        if let Some(root) = tree.root.as_ref() {
+                            root.check_invariants(&config);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tree_set_algebra_shared() {
+        let config = BTreeConfig { max_degree: 4 };
+        let base = BTree::from_sorted_iter((0..1000).map(|i| (i, i)), config).unwrap();
+        let mut a = base.clone();
+        let mut b = base.clone();
+        a.insert(10, -10);
+        a.delete_by_key(&20);
+        b.insert(990, -990);
+        b.delete_by_key(&980);
+
+        // resolve is only called for the few keys on the changed paths
+        let mut resolved = 0;
+        let union = a.union(&b, |_, x, _| {
+            resolved += 1;
+            *x
+        });
+        assert!(resolved < 50);
+        assert_eq!(union.iter().len(), 1000);
+        assert_eq!(*union.get_by_key(&10).unwrap(), -10);
+        assert_eq!(*union.get_by_key(&20).unwrap(), 20);
+        assert_eq!(*union.get_by_key(&980).unwrap(), 980);
+        assert!(new_nodes(&union, &[&a, &b]) < 30);
+
+        assert!(a.difference(&a).root.is_none());
+        assert!(Arc::ptr_eq(
+            a.intersection(&a).root.as_ref().unwrap(),
+            a.root.as_ref().unwrap()
+        ));
+        let difference = a.difference(&b);
+        assert_eq!(
+            difference.iter().copied().collect::<Vec<_>>(),
+            vec![(980, 980)]
+        );
+    }
+
+    #[test]
+    fn test_tree_diff() {
+        use std::collections::BTreeMap;
+
+        for max_degree in [3, 4, 7] {
+            let config = BTreeConfig { max_degree };
+            let base = BTree::from_sorted_iter((0..500).map(|i| (i * 2, i)), config).unwrap();
+
+            // insert, update and delete in a scrambled order, diff against
+            // every intermediate version
+            let mut new = base.clone();
+            let mut versions = vec![];
+            for i in 0..300 {
+                let k = (i * 37) % 1100;
+                match i % 3 {
+                    0 => {
+                        new.insert(k, -k);
+                    }
+                    1 => {
+                        new.delete_by_key(&k);
+                    }
+                    _ => {
+                        new.insert(k, k / 2);
+                    }
+                }
+                versions.push(new.clone());
+            }
+
+            let base_map = base.iter().copied().collect::<BTreeMap<_, _>>();
+            for version in versions.iter().step_by(13) {
+                let new_map = version.iter().copied().collect::<BTreeMap<_, _>>();
+                let mut expected = vec![];
+                for k in base_map
+                    .keys()
+                    .chain(new_map.keys())
+                    .collect::<std::collections::BTreeSet<_>>()
+                {
+                    match (base_map.get(k), new_map.get(k)) {
+                        (Some(v), None) => expected.push(DiffItem::Removed(k, v)),
+                        (None, Some(v)) => expected.push(DiffItem::Added(k, v)),
+                        (Some(old), Some(new)) if old != new => {
+                            expected.push(DiffItem::Changed(k, old, new))
+                        }
+                        _ => {}
+                    }
+                }
+
+                assert_eq!(base.diff(version).collect::<Vec<_>>(), expected);
+            }
+        }
+
+        // only values on the changed path are compared
+        static EQ_COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        #[derive(Clone)]
+        struct Value(i32);
+        impl PartialEq for Value {
+            fn eq(&self, other: &Self) -> bool {
+                EQ_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                self.0 == other.0
+            }
+        }
+        let config = BTreeConfig { max_degree: 8 };
+        let old = BTree::from_sorted_iter((0..10000).map(|i| (i, Value(i))), config).unwrap();
+        let mut new = old.clone();
+        new.insert(5000, Value(0));
+        assert_eq!(old.diff(&new).count(), 1);
+        assert!(EQ_COUNTER.load(std::sync::atomic::Ordering::Relaxed) < 50);
+
+        let tree = (0..10).map(|i| (i, i)).collect::<BTree<i32, i32>>();
+        let empty = BTree::new();
+        assert_eq!(tree.diff(&tree).count(), 0);
+        assert_eq!(tree.diff(&empty).count(), 10);
+        assert_eq!(empty.diff(&tree).count(), 10);
+    }
+
+    #[test]
+    fn test_tree_merge3() {
+        let config = BTreeConfig { max_degree: 4 };
+        let base = BTree::from_sorted_iter((0..200).map(|i| (i, i)), config).unwrap();
+
+        let mut ours = base.clone();
+        let mut theirs = base.clone();
+
+        // changed by one side only
+        ours.insert(10, -10);
+        ours.delete_by_key(&11);
+        ours.insert(1000, 1000);
+        theirs.insert(20, -20);
+        theirs.delete_by_key(&21);
+        theirs.insert(2000, 2000);
+        // changed by both sides the same way
+        ours.insert(30, -30);
+        theirs.insert(30, -30);
+        ours.delete_by_key(&31);
+        theirs.delete_by_key(&31);
+        // conflicts
+        ours.insert(40, 1);
+        theirs.insert(40, 2);
+        ours.delete_by_key(&41);
+        theirs.insert(41, 3);
+        ours.insert(3000, 4);
+        theirs.insert(3000, 5);
+
+        let mut conflicts = vec![];
+        let merged = BTree::merge3(&base, &ours, &theirs, |conflict| {
+            conflicts.push((
+                *conflict.key,
+                conflict.base.copied(),
+                conflict.ours.copied(),
+                conflict.theirs.copied(),
+            ));
+            conflict.theirs.copied()
+        });
+        assert_eq!(
+            conflicts,
+            vec![
+                (40, Some(40), Some(1), Some(2)),
+                (41, Some(41), None, Some(3)),
+                (3000, None, Some(4), Some(5)),
+            ]
+        );
+
+        merged.root.as_ref().unwrap().check_invariants(&config);
+        let mut expected = (0..200)
+            .map(|i| (i, i))
+            .collect::<std::collections::BTreeMap<_, _>>();
+        expected.extend([(10, -10), (20, -20), (30, -30), (40, 2), (41, 3)]);
+        expected.extend([(1000, 1000), (2000, 2000), (3000, 5)]);
+        for k in [11, 21, 31] {
+            expected.remove(&k);
+        }
+        assert!(merged.iter().map(|(k, v)| (k, v)).eq(expected.iter()));
+
+        // merging with unchanged sides gives back the other side
+        assert_eq!(
+            BTree::merge3(&base, &base, &theirs, |_| None)
+                .diff(&theirs)
+                .count(),
+            0
+        );
+        assert_eq!(
+            BTree::merge3(&base, &ours, &base, |_| None)
+                .diff(&ours)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_tree_borrowed_key() {
+        let mut tree = BTree::<String, i32>::new_with_config(BTreeConfig { max_degree: 4 });
+        for (i, word) in ["apple", "banana", "cherry", "date", "fig"]
+            .iter()
+            .enumerate()
+        {
+            tree.insert(word.to_string(), i as i32);
+        }
+
+        // look up with &str, no String allocated
+        assert_eq!(*tree.get_by_key("cherry").unwrap(), 2);
+        assert_eq!(tree.rank("cherry"), Ok(2));
+        assert_eq!(tree.rank("coconut"), Err(3));
+        *tree.get_mut("date").unwrap() += 10;
+        assert_eq!(*tree.get_by_key("date").unwrap(), 13);
+        assert_eq!(
+            tree.range::<str, _>((Bound::Included("b"), Bound::Excluded("d")))
+                .map(|(k, _)| k.as_str())
+                .collect::<Vec<_>>(),
+            vec!["banana", "cherry"]
+        );
+        assert_eq!(tree.delete_by_key("apple"), Some(("apple".to_string(), 0)));
+
+        let upper = tree.split_off("cherry");
+        assert_eq!(tree.keys().collect::<Vec<_>>(), vec!["banana"]);
+        assert_eq!(
+            upper.keys().collect::<Vec<_>>(),
+            vec!["cherry", "date", "fig"]
+        );
+    }
+
+    #[test]
+    fn test_tree_comparator() {
+        let config = BTreeConfig { max_degree: 4 };
+
+        // closure comparator, keys compared case insensitively
+        let mut tree = BTree::new_with_comparator(config, |a: &String, b: &String| {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        });
+        for word in ["b", "A", "c", "B", "a"] {
+            *tree.entry(word.to_string()).or_insert(0) += 1;
+        }
+        assert_eq!(
+            tree.iter().cloned().collect::<Vec<_>>(),
+            vec![
+                ("A".to_string(), 2),
+                ("b".to_string(), 2),
+                ("c".to_string(), 1)
+            ]
+        );
+        assert_eq!(tree.get_by_key(&"C".to_string()), Some(&1));
+        assert_eq!(tree.rank(&"B".to_string()), Ok(1));
+        assert!(tree.delete_by_key(&"a".to_string()).is_some());
+        assert_eq!(tree.iter().len(), 2);
+
+        // stateful comparator, order is decided at runtime
+        #[derive(Clone)]
+        struct Directed {
+            descending: bool,
+        }
+
+        impl Comparator<i32> for Directed {
+            fn cmp(&self, a: &i32, b: &i32) -> Ordering {
+                if self.descending {
+                    b.cmp(a)
+                } else {
+                    a.cmp(b)
+                }
+            }
+        }
+
+        let comparator = Directed { descending: true };
+        let mut tree = BTree::new_with_comparator(config, comparator.clone());
+        for i in 0..200 {
+            tree.insert(i, i);
+        }
+        assert!(tree.keys().copied().eq((0..200).rev()));
+        assert!(tree
+            .range((Bound::Included(150), Bound::Included(100)))
+            .map(|(k, _)| *k)
+            .eq((100..=150).rev()));
+        for i in (0..200).step_by(2) {
+            assert_eq!(tree.delete_by_key(&i), Some((i, i)));
+        }
+        assert!(tree
+            .keys()
+            .copied()
+            .eq((0..200).rev().filter(|i| i % 2 == 1)));
+
+        let upper = tree.split_off(&100);
+        assert!(tree.keys().all(|k| *k > 100));
+        assert!(upper.keys().all(|k| *k < 100));
+        let joined = BTree::join(tree.clone(), upper);
+        assert_eq!(joined.iter().len(), 100);
+
+        let other = BTree::from_sorted_iter_with_comparator(
+            (50..150).rev().map(|i| (i, -i)),
+            config,
+            comparator.clone(),
+        )
+        .unwrap();
+        let union = joined.union(&other, |_, a, _| *a);
+        assert!(union.keys().copied().eq((0..200)
+            .rev()
+            .filter(|i| i % 2 == 1 || (50..150).contains(i))));
+        assert_eq!(joined.intersection(&other).iter().len(), 50);
+        assert!(joined
+            .diff(&union)
+            .all(|item| matches!(item, DiffItem::Added(..))));
+
+        // range bounds follow the comparator's order, not the keys' Ord
+        let mut tree = BTree::new_with_comparator(config, |a: &i32, b: &i32| b.cmp(a));
+        for i in 0..100 {
+            tree.insert(i, ());
+        }
+        let found = tree.find_key_range(|k| match k {
+            61.. => PredicateResult::Left,
+            30..=60 => PredicateResult::Match,
+            _ => PredicateResult::Right,
+        });
+        assert_eq!(found.start_key(), Some(&60));
+        assert_eq!(found.end_key(), Some(&30));
+        assert_eq!(found.n(), 31);
+        for lo in (0..100).step_by(7) {
+            let hi = (lo + 13).min(99);
+            let found = tree.find_key_range(|k| {
+                if *k > hi {
+                    PredicateResult::Left
+                } else if *k >= lo {
+                    PredicateResult::Match
+                } else {
+                    PredicateResult::Right
+                }
+            });
+            assert_eq!(found.start_key(), Some(&hi));
+            assert_eq!(found.end_key(), Some(&lo));
+            assert_eq!(found.n(), (hi - lo + 1) as usize);
+        }
+
+        let unsorted =
+            BTree::from_sorted_iter_with_comparator([(1, ()), (2, ())], config, comparator);
+        assert_eq!(
+            unsorted.unwrap_err(),
+            SortedIterError::Unsorted { offset: 1 }
+        );
+    }
+
+    #[test]
+    fn test_tree_pop() {
+        for max_degree in [3, 4, 5, 8] {
+            let config = BTreeConfig { max_degree };
+            let mut tree = BTree::<i32, i32>::new_with_config(config);
+            assert!(tree.is_empty());
+            assert_eq!(tree.first(), None);
+            assert_eq!(tree.last(), None);
+            assert_eq!(tree.pop_first(), None);
+            assert_eq!(tree.pop_last(), None);
+
+            for i in 0..300 {
+                tree.insert(i, i * 10);
+            }
+            assert_eq!(tree.len(), 300);
+            assert_eq!(tree.first(), Some(&(0, 0)));
+            assert_eq!(tree.last(), Some(&(299, 2990)));
+
+            let snapshot = tree.snapshot();
+            let (mut low, mut high) = (0, 299);
+            while low <= high {
+                if low % 3 == 0 {
+                    assert_eq!(tree.pop_last(), Some((high, high * 10)));
+                    high -= 1;
+                } else {
+                    assert_eq!(tree.pop_first(), Some((low, low * 10)));
+                    low += 1;
+                }
+                assert_eq!(tree.len() as i32, high - low + 1);
+                if let Some(root) = tree.root.as_ref() {
+                    root.check_invariants(&config);
+                    assert_eq!(tree.first().unwrap().0, low);
+                    assert_eq!(tree.last().unwrap().0, high);
+                }
+            }
+            assert!(tree.is_empty());
+            assert_eq!(snapshot.len(), 300);
+            assert!(snapshot.keys().copied().eq(0..300));
+        }
+    }
+
+    #[test]
+    fn test_tree_retain() {
+        for max_degree in [3, 4, 5, 8] {
+            let config = BTreeConfig { max_degree };
+            for n in [0, 1, 10, 100, 1000] {
+                let tree = BTree::from_sorted_iter((0..n).map(|i| (i, i)), config).unwrap();
+                for modulo in [1, 2, 3, 7, 50, 2000] {
+                    let mut retained = tree.clone();
+                    let mut visited = vec![];
+                    retained.retain(|k, _| {
+                        visited.push(*k);
+                        k % modulo != 0
+                    });
+                    assert!(visited.iter().copied().eq(0..n));
+                    if let Some(root) = retained.root.as_ref() {
+                        root.check_invariants(&config);
+                    }
+                    assert!(retained
+                        .keys()
+                        .copied()
+                        .eq((0..n).filter(|k| k % modulo != 0)));
+                    assert_eq!(tree.len(), n as usize);
+                }
+            }
+
+            // only the path to removed keys is rebuilt
+            let tree = BTree::from_sorted_iter((0..1000).map(|i| (i, i)), config).unwrap();
+            let height = tree.root.as_ref().unwrap().height();
+            let mut retained = tree.clone();
+            retained.retain(|k, _| *k != 500);
+            retained.root.as_ref().unwrap().check_invariants(&config);
+            assert_eq!(retained.len(), 999);
+            assert!(new_nodes(&retained, &[&tree]) <= 2 * (height + 1));
+
+            let mut retained = tree.clone();
+            retained.retain(|_, _| true);
+            assert_eq!(new_nodes(&retained, &[&tree]), 0);
+        }
+    }
+
+    #[test]
+    fn test_tree_extract_if() {
+        let config = BTreeConfig { max_degree: 4 };
+        let mut tree = BTree::from_sorted_iter((0..200).map(|i| (i, i)), config).unwrap();
+        let snapshot = tree.snapshot();
+
+        // dropped early, the rest stays in tree
+        let mut extract = tree.extract_if(|k, _| k % 3 == 0);
+        assert_eq!(extract.next(), Some((0, 0)));
+        assert_eq!(extract.next(), Some((3, 3)));
+        assert_eq!(tree.len(), 198);
+        assert!(tree.get_by_key(&6).is_some());
+
+        let extracted = tree.extract_if(|k, _| k % 3 == 0).collect::<Vec<_>>();
+        assert!(extracted.iter().map(|(k, _)| *k).eq((6..200).step_by(3)));
+        tree.root.as_ref().unwrap().check_invariants(&config);
+        assert!(tree.keys().copied().eq((0..200).filter(|k| k % 3 != 0)));
+        assert_eq!(tree.extract_if(|_, _| false).count(), 0);
+        assert_eq!(tree.extract_if(|_, _| true).count(), 133);
+        assert!(tree.is_empty());
+        assert_eq!(snapshot.len(), 200);
+    }
+
+    #[test]
+    fn test_tree_remove_range() {
+        for max_degree in [3, 4, 5, 8] {
+            let config = BTreeConfig { max_degree };
+            let tree = BTree::from_sorted_iter((0..500).map(|i| (i, i)), config).unwrap();
+            let height = tree.root.as_ref().unwrap().height();
+            for (start, end) in [
+                (0, 0),
+                (0, 500),
+                (0, 1),
+                (499, 500),
+                (100, 400),
+                (250, 251),
+                (300, 200),
+            ] {
+                let mut rest = tree.clone();
+                let removed = rest.remove_range(start..end);
+                let expected = start..end.max(start);
+                assert!(removed.keys().copied().eq(expected.clone()));
+                assert!(rest
+                    .keys()
+                    .copied()
+                    .eq((0..500).filter(|k| !expected.contains(k))));
+                for part in [&rest, &removed] {
+                    if let Some(root) = part.root.as_ref() {
+                        root.check_invariants(&config);
+                    }
+                }
+                // only nodes on the two split paths are new
+                assert!(new_nodes(&rest, &[&tree]) <= 4 * (height + 1));
+            }
+
+            let mut rest = tree.clone();
+            let removed = rest.remove_range((Bound::Excluded(99), Bound::Included(199)));
+            assert!(removed.keys().copied().eq(100..200));
+            assert_eq!(rest.remove_range(1000..).len(), 0);
+            assert_eq!(rest.remove_range(..).len(), 400);
+            assert!(rest.is_empty());
+
+            let mut rest = tree.clone();
+            let drained = rest.drain_range(10..20);
+            assert_eq!(drained.len(), 10);
+            assert!(drained.rev().eq((10..20).rev().map(|i| (i, i))));
+            assert_eq!(rest.len(), 490);
+            assert!(rest
+                .drain_range(..)
+                .eq((0..10).chain(20..500).map(|i| (i, i))));
+            assert!(rest.is_empty());
+            assert_eq!(tree.len(), 500);
+        }
+    }
+
+    #[test]
+    fn test_tree_cursor() {
+        for max_degree in [3, 4, 5, 8] {
+            let config = BTreeConfig { max_degree };
+            let tree = BTree::from_sorted_iter((0..300).map(|i| (i * 2, i)), config).unwrap();
+
+            let mut cursor = tree.cursor_at_offset(0);
+            for offset in 0..300 {
+                assert_eq!(cursor.offset(), Some(offset));
+                assert_eq!(cursor.key(), Some(&(offset as i32 * 2)));
+                assert_eq!(cursor.value(), Some(&(offset as i32)));
+                assert_eq!(
+                    cursor.peek_prev(),
+                    offset.checked_sub(1).and_then(|o| tree.get_by_offset(o))
+                );
+                assert_eq!(cursor.peek_next(), tree.get_by_offset(offset + 1));
+                cursor.move_next();
+            }
+            assert_eq!(cursor.offset(), None);
+            assert_eq!(cursor.key(), None);
+            assert_eq!(cursor.peek_next(), tree.first());
+            assert_eq!(cursor.peek_prev(), tree.last());
+
+            for offset in (0..300).rev() {
+                cursor.move_prev();
+                assert_eq!(cursor.offset(), Some(offset));
+                assert_eq!(cursor.key_value(), tree.get_by_offset(offset));
+            }
+            cursor.move_prev();
+            assert_eq!(cursor.offset(), None);
+            cursor.move_next();
+            assert_eq!(cursor.key(), Some(&0));
+
+            assert_eq!(tree.lower_bound(Bound::Included(&7)).key(), Some(&8));
+            assert_eq!(tree.lower_bound(Bound::Excluded(&8)).key(), Some(&10));
+            assert_eq!(tree.lower_bound(Bound::Unbounded).key(), Some(&0));
+            assert_eq!(tree.upper_bound(Bound::Included(&8)).key(), Some(&8));
+            assert_eq!(tree.upper_bound(Bound::Excluded(&8)).key(), Some(&6));
+            assert_eq!(tree.upper_bound(Bound::Unbounded).key(), Some(&598));
+            assert_eq!(tree.upper_bound(Bound::Excluded(&0)).offset(), None);
+            assert_eq!(tree.cursor_at_offset(300).offset(), None);
+        }
+
+        let empty = BTree::<i32, i32>::new();
+        let mut cursor = empty.cursor_at_offset(0);
+        assert_eq!(cursor.key(), None);
+        cursor.move_next();
+        cursor.move_prev();
+        assert_eq!(cursor.peek_next(), None);
+        assert_eq!(cursor.peek_prev(), None);
+    }
+
+    #[test]
+    fn test_tree_cursor_mut() {
+        for max_degree in [3, 4, 5, 8] {
+            let config = BTreeConfig { max_degree };
+            let mut tree = BTree::<i32, i32>::new_with_config(config);
+            let mut expected = vec![];
+
+            // build 0, 100, 200, ... by inserting around a cursor
+            let mut cursor = tree.cursor_mut_at_offset(0);
+            cursor.insert_after(10000, 0);
+            cursor.move_next();
+            for i in 0..100 {
+                cursor.insert_before(i * 100, i);
+                assert_eq!(cursor.key(), Some(&10000));
+            }
+            cursor.move_prev();
+            for i in 0..50 {
+                cursor.insert_after(9999 - i, i);
+                assert_eq!(cursor.key(), Some(&9900));
+            }
+            expected.extend((0..100).map(|i| i * 100));
+            expected.extend((0..50).map(|i| 9999 - i));
+            expected.push(10000);
+            expected.sort();
+            tree.root.as_ref().unwrap().check_invariants(&config);
+            assert!(tree.keys().eq(expected.iter()));
+
+            let snapshot = tree.snapshot();
+
+            // remove every other key value walking forward
+            let mut cursor = tree.cursor_mut_at_offset(0);
+            let mut removed = vec![];
+            while let Some((key, _)) = cursor.remove_current() {
+                removed.push(key);
+                match cursor.value_mut() {
+                    Some(value) => *value = -1,
+                    // at ghost, moving on would wrap around
+                    None => break,
+                }
+                cursor.move_next();
+            }
+            assert_eq!(cursor.remove_current(), None);
+            tree.root.as_ref().unwrap().check_invariants(&config);
+            assert!(removed.iter().eq(expected.iter().step_by(2)));
+            assert!(tree.keys().eq(expected.iter().skip(1).step_by(2)));
+            assert!(tree.values().all(|v| *v == -1));
+            assert!(snapshot.keys().eq(expected.iter()));
+
+            let offset = tree.keys().take_while(|k| **k < 5000).count();
+            let mut cursor = tree.lower_bound_mut(Bound::Included(&5000));
+            assert_eq!(cursor.offset(), Some(offset));
+            cursor.move_prev();
+            let key = *cursor.key().unwrap();
+            cursor.insert_after(key + 1, 0);
+            assert_eq!(cursor.peek_next().unwrap().0, key + 1);
+            let mut cursor = tree.upper_bound_mut(Bound::Unbounded);
+            while cursor.remove_current().is_some() {
+                cursor.move_prev();
+            }
+            assert!(tree.is_empty());
+
+            // path kept in place must match a fresh seek after every step
+            let mut expected = (0..100).map(|i| i << 20).collect::<Vec<i64>>();
+            let mut tree =
+                BTree::from_sorted_iter(expected.iter().map(|k| (*k, ())), config).unwrap();
+            let mut cursor = tree.cursor_mut_at_offset(0);
+            for step in 0..3000 {
+                let offset = cursor.offset().unwrap_or(expected.len());
+                let prev = cursor.peek_prev().map_or(-1 << 40, |(k, _)| *k);
+                let current = cursor.key().map_or(1 << 40, |k| *k);
+                let next = cursor.peek_next().map_or(1 << 40, |(k, _)| *k);
+                match step * 7 % 5 {
+                    0 => cursor.move_next(),
+                    1 => cursor.move_prev(),
+                    2 if current - prev > 1 => {
+                        cursor.insert_before((prev + current) / 2, ());
+                        expected.insert(offset, (prev + current) / 2);
+                    }
+                    3 if cursor.key().is_some() && next - current > 1 => {
+                        cursor.insert_after((current + next) / 2, ());
+                        expected.insert(offset + 1, (current + next) / 2);
+                    }
+                    4 if step % 3 == 0 => {
+                        if cursor.remove_current().is_some() {
+                            expected.remove(offset);
+                        }
+                    }
+                    _ => cursor.move_next(),
+                }
+
+                let offset = cursor.offset().unwrap_or(expected.len());
+                let seeked = Cursor::new(cursor.tree.root.as_deref(), offset);
+                assert_eq!(cursor.path, seeked.path());
+                assert_eq!(cursor.key(), expected.get(offset));
+            }
+            tree.root.as_ref().unwrap().check_invariants(&config);
+            assert!(tree.keys().eq(expected.iter()));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "key must be greater than the key before it")]
+    fn test_tree_cursor_mut_order() {
+        let mut tree = (0..10).map(|i| (i * 10, ())).collect::<BTree<i32, ()>>();
+        let mut cursor = tree.cursor_mut_at_offset(3);
+        cursor.insert_before(15, ());
+    }
+
+    #[test]
+    fn test_tree_measure() {
+        struct Len;
+        impl Measure<i32, Vec<u8>> for Len {
+            fn measure(_key: &i32, value: &Vec<u8>) -> usize {
+                value.len()
+            }
+        }
+
+        struct Unit;
+        impl Measure<i32, Vec<u8>> for Unit {
+            fn measure(_key: &i32, _value: &Vec<u8>) -> usize {
+                1
+            }
+        }
+
+        let config = BTreeConfig { max_degree: 4 };
+        let mut tree: BTree<i32, Vec<u8>, OrdComparator, Measured<Len>> =
+            BTree::new_with_summary(config, OrdComparator);
+        let mut unit: BTree<i32, Vec<u8>, OrdComparator, Measured<Unit>> =
+            BTree::new_with_summary(config, OrdComparator);
+        for i in 0..300 {
+            // some key values measure 0
+            let key = (i * 37) % 300;
+            tree.insert(key, vec![0; (key % 7) as usize]);
+            unit.insert(key, vec![0; (key % 7) as usize]);
+        }
+        for i in 0..100 {
+            tree.delete_by_key(&(i * 3));
+            unit.delete_by_offset(i as usize);
+        }
+        tree.insert(1, vec![0; 100]);
+        let total = tree.total_measure();
+        let right = tree.split_off(&150);
+        right.root.as_ref().unwrap().check_summary();
+        assert_eq!(tree.total_measure() + right.total_measure(), total);
+        let mut tree = BTree::join(tree, right);
+        tree.retain(|key, _| key % 10 != 0);
+
+        let root = tree.root.as_ref().unwrap();
+        root.check_invariants(&config);
+        root.check_summary();
+        unit.root.as_ref().unwrap().check_summary();
+
+        // check against linear scan
+        let mut start = 0;
+        for key_value in tree.iter() {
+            for x in start..start + key_value.1.len() {
+                assert_eq!(tree.get_by_measure(x), Some((start, key_value)));
+            }
+            start += key_value.1.len();
+        }
+        assert_eq!(tree.total_measure(), start);
+        assert_eq!(tree.get_by_measure(start), None);
+
+        // unit measure locates same key values as offset
+        assert_eq!(unit.total_measure(), unit.len());
+        for offset in 0..unit.len() {
+            let key_value = unit.get_by_offset(offset);
+            assert_eq!(
+                unit.get_by_measure(offset),
+                key_value.map(|kv| (offset, kv))
+            );
+        }
+
+        let empty: BTree<i32, Vec<u8>, OrdComparator, Measured<Len>> = BTree::default();
+        assert_eq!(empty.total_measure(), 0);
+        assert_eq!(empty.get_by_measure(0), None);
+    }
+
+    #[test]
+    fn test_tree_fold() {
+        struct Value;
+        impl Field<i32, i64> for Value {
+            type Value = i64;
+            fn get(_key: &i32, value: &i64) -> i64 {
+                *value
+            }
+        }
+        type Stats = (Sum<i64, Value>, (Min<i64, Value>, Max<i64, Value>));
+
+        let naive = |tree: &BTree<i32, i64, OrdComparator, Stats>, start: usize, end: usize| {
+            let values = tree
+                .iter_offsets(start..end)
+                .map(|(_, v)| *v)
+                .collect::<Vec<_>>();
+            let sum = values.iter().sum::<i64>();
+            (
+                sum,
+                values.iter().min().copied(),
+                values.iter().max().copied(),
+            )
+        };
+        let fields = |stats: &Stats| {
+            let (sum, (min, max)) = stats;
+            (*sum.value(), min.value().copied(), max.value().copied())
+        };
+
+        for_each_degree_random(&[3, 4, 5], |config, next| {
+            let mut tree: BTree<i32, i64, OrdComparator, Stats> =
+                BTree::new_with_summary(config, OrdComparator);
+
+            let mut snapshot = None;
+            for i in 0..2000 {
+                match next(6) {
+                    0..=2 => {
+                        let key = next(1000) as i32;
+                        tree.insert(key, next(2000) as i64 - 1000);
+                    }
+                    3 => {
+                        tree.delete_by_key(&(next(1000) as i32));
+                    }
+                    4 => {
+                        tree.delete_by_offset(next(tree.len()));
+                    }
+                    _ => {
+                        let right = tree.split_off(&(next(1000) as i32));
+                        tree = BTree::join(tree, right);
+                    }
+                }
+                if i == 1000 {
+                    snapshot = Some((tree.clone(), tree.summary()));
+                }
+            }
+            tree.retain(|key, _| key % 5 != 0);
+            let root = tree.root.as_ref().unwrap();
+            root.check_invariants(&config);
+            root.check_summary();
+
+            let len = tree.len();
+            assert_eq!(fields(&tree.summary()), naive(&tree, 0, len));
+            for _ in 0..500 {
+                let (a, b) = (next(len + 1), next(len + 1));
+                let (start, end) = (a.min(b), a.max(b));
+                assert_eq!(
+                    fields(&tree.fold_offsets(start..end)),
+                    naive(&tree, start, end)
+                );
+
+                let (a, b) = (next(1000) as i32, next(1000) as i32);
+                let (low, high) = (a.min(b), a.max(b));
+                let start = tree.rank(&low).unwrap_or_else(|offset| offset);
+                let end = tree.rank(&high).map_or_else(|offset| offset, |o| o + 1);
+                assert_eq!(
+                    fields(&tree.fold_range(low..=high)),
+                    naive(&tree, start, end)
+                );
+            }
+            assert_eq!(fields(&tree.fold_offsets(len..)), (0, None, None));
+            assert_eq!(fields(&tree.fold_range(2000..)), (0, None, None));
+
+            let (snapshot, summary) = snapshot.unwrap();
+            assert_eq!(snapshot.summary(), summary);
+            snapshot.root.as_ref().unwrap().check_summary();
+        });
+    }
+
+    #[test]
+    fn test_set() {
+        let config = BTreeConfig { max_degree: 4 };
+        let mut set = BTreeSet::new_with_config(config);
+        for i in (0..100).rev() {
+            assert!(set.insert(i * 2));
+        }
+        assert!(!set.insert(0));
+        assert_eq!(set.len(), 100);
+        assert!(set.contains(&10));
+        assert!(!set.contains(&11));
+        assert_eq!(set.rank(&10), Ok(5));
+        assert_eq!(set.rank(&11), Err(6));
+        assert_eq!(set.select(5), Some(&10));
+        assert_eq!(set.first(), Some(&0));
+        assert_eq!(set.last(), Some(&198));
+        assert!(set.range(10..20).copied().eq((10..20).step_by(2)));
+        assert!(set.iter_offsets(95..).copied().eq((190..200).step_by(2)));
+
+        let snapshot = set.clone();
+        assert!(set.remove(&10));
+        assert!(!set.remove(&10));
+        assert_eq!(set.take(&12), Some(12));
+        assert_eq!(set.pop_first(), Some(0));
+        assert_eq!(set.pop_last(), Some(198));
+        assert_eq!(set.len(), 96);
+        assert_eq!(snapshot.len(), 100);
+
+        let odd = BTreeSet::from_sorted_iter((0..100).map(|i| i * 2 + 1), config).unwrap();
+        let union = snapshot.union(&odd);
+        assert!(union.iter().copied().eq(0..200));
+        assert!(union.intersection(&odd).iter().eq(odd.iter()));
+        assert!(union.difference(&odd).iter().eq(snapshot.iter()));
+        assert!(snapshot.symmetric_difference(&odd).iter().eq(union.iter()));
+
+        let mut lower = union.clone();
+        let mut upper = lower.split_off(&100);
+        assert!(lower.iter().copied().eq(0..100));
+        lower.append(&mut upper);
+        assert!(upper.is_empty());
+        assert!(lower.iter().eq(union.iter()));
+
+        let found = union.find_key_range(|k| match k {
+            k if *k < 50 => PredicateResult::Left,
+            k if *k >= 60 => PredicateResult::Right,
+            _ => PredicateResult::Match,
+        });
+        assert_eq!((found.start_key(), found.end_key()), (Some(&50), Some(&59)));
+        assert_eq!(found.n(), 10);
+
+        let mut reversed = BTreeSet::new_with_comparator(config, |a: &i32, b: &i32| b.cmp(a));
+        for i in 0..100 {
+            reversed.insert(i);
+        }
+        assert!(reversed.iter().copied().eq((0..100).rev()));
+        assert_eq!(reversed.select(0), Some(&99));
+        let found = reversed.find_key_range(|k| match k {
+            k if *k >= 60 => PredicateResult::Left,
+            k if *k < 50 => PredicateResult::Right,
+            _ => PredicateResult::Match,
+        });
+        assert_eq!((found.start_key(), found.end_key()), (Some(&59), Some(&50)));
+        assert_eq!(found.n(), 10);
+    }
+
+    #[test]
+    fn test_sequence() {
+        for_each_degree_random(&[3, 4, 5, 8], |config, next| {
+            let mut seq = Sequence::new_with_config(config);
+            let mut expected = std::collections::VecDeque::new();
+
+            // random operations checked against VecDeque
+            for i in 0..2000 {
+                match next(7) {
+                    0 | 1 => {
+                        let index = next(expected.len() + 1);
+                        seq.insert_at(index, i);
+                        expected.insert(index, i);
+                    }
+                    2 => {
+                        let index = next(expected.len());
+                        assert_eq!(seq.remove_at(index), expected.remove(index));
+                    }
+                    3 => {
+                        seq.push_front(i);
+                        expected.push_front(i);
+                    }
+                    4 => {
+                        seq.push_back(i);
+                        expected.push_back(i);
+                    }
+                    5 => {
+                        let index = next(expected.len());
+                        assert_eq!(seq.set(index, i), expected.get(index).copied());
+                        if let Some(value) = expected.get_mut(index) {
+                            *value = i;
+                        }
+                    }
+                    _ => {
+                        assert_eq!(seq.pop_front(), expected.pop_front());
+                        assert_eq!(seq.pop_back(), expected.pop_back());
+                    }
+                }
+            }
+            assert_eq!(seq.len(), expected.len());
+            assert!(seq.iter().eq(expected.iter()));
+            assert_eq!(seq.first(), expected.front());
+            assert_eq!(seq.last(), expected.back());
+            seq.tree.root.as_ref().unwrap().check_structure(&config);
+
+            let snapshot = seq.clone();
+            for index in [0, 1, seq.len() / 2, seq.len()] {
+                let (head, tail) = seq.split_at(index);
+                assert_eq!(head.len(), index);
+                assert!(tail.iter().eq(expected.iter().skip(index)));
+                let joined = Sequence::concat(head, tail);
+                joined.tree.root.as_ref().unwrap().check_structure(&config);
+                assert!(joined.iter().eq(expected.iter()));
+            }
+            assert!(seq.range(10..20).eq(expected.range(10..20)));
+
+            let mut other = (0..100).collect::<Sequence<i32>>();
+            seq.append(&mut other);
+            assert!(other.is_empty());
+            assert!(seq
+                .iter()
+                .eq(expected.iter().chain((0..100).collect::<Vec<_>>().iter())));
+            assert_eq!(snapshot.len(), expected.len());
+        });
+
+        // zero sized items still get a usable default config
+        let mut units = Sequence::<()>::new();
+        for _ in 0..10 {
+            units.push_back(());
+        }
+        assert_eq!(units.len(), 10);
+        let mut set = BTreeSet::<()>::new();
+        assert!(set.insert(()));
+        assert!(!set.insert(()));
+
+        // other is rebuilt under self's config when degrees differ
+        for (self_degree, other_degree) in [(3, 16), (16, 3)] {
+            let config = BTreeConfig {
+                max_degree: self_degree,
+            };
+            let mut seq = Sequence::new_with_config(config);
+            let mut other = Sequence::new_with_config(BTreeConfig {
+                max_degree: other_degree,
+            });
+            for i in 0..1000 {
+                if i < 300 {
+                    seq.push_back(i);
+                } else {
+                    other.push_back(i);
+                }
+            }
+            seq.append(&mut other);
+            assert!(other.is_empty());
+            assert_eq!(seq.tree.config.max_degree, self_degree);
+            seq.tree.root.as_ref().unwrap().check_structure(&config);
+            assert!(seq.iter().copied().eq(0..1000));
+        }
+    }
+
+    #[test]
+    fn test_rope() {
+        let pieces = ["a", "bc", "\n", "héllo", "中文\n", "😀", "line\nline\n"];
+        let floor_boundary = |text: &str, mut byte: usize| {
+            while !text.is_char_boundary(byte) {
+                byte -= 1;
+            }
+            byte
+        };
+
+        for_each_degree_random(&[3, 4, 8], |config, next| {
+            let mut rope = Rope::new_with_config(config);
+            let mut expected = String::new();
+            let mut snapshots = vec![];
+
+            // random edits checked against String
+            for i in 0..1500 {
+                match next(6) {
+                    0..=2 => {
+                        let repeat = if next(10) == 0 { 300 } else { 1 + next(5) };
+                        let text = pieces[next(pieces.len())].repeat(repeat);
+                        let byte = floor_boundary(&expected, next(expected.len() + 1));
+                        rope.insert(byte, &text);
+                        expected.insert_str(byte, &text);
+                    }
+                    3 | 4 => {
+                        let start = floor_boundary(&expected, next(expected.len() + 1));
+                        let end = (start + next(200)).min(expected.len());
+                        let end = floor_boundary(&expected, end);
+                        rope.remove(start..end);
+                        expected.replace_range(start..end, "");
+                    }
+                    _ => {
+                        let start = floor_boundary(&expected, next(expected.len() + 1));
+                        let end = floor_boundary(&expected, next(expected.len() + 1));
+                        let (start, end) = (start.min(end), start.max(end));
+                        let slice = rope.slice(start..end);
+                        assert_eq!(slice.to_string(), expected[start..end]);
+                        assert_eq!(slice.len_chars(), expected[start..end].chars().count());
+                    }
+                }
+                if i % 100 == 0 {
+                    snapshots.push((rope.clone(), expected.clone()));
+                }
+            }
+
+            assert_eq!(rope.to_string(), expected);
+            assert_eq!(rope.len_bytes(), expected.len());
+            assert_eq!(rope.len_chars(), expected.chars().count());
+            assert_eq!(rope.len_lines(), expected.split('\n').count());
+            let root = rope.root.as_ref().unwrap();
+            root.check_structure(&config);
+            root.check_summary();
+
+            let line_starts = std::iter::once(0)
+                .chain(expected.match_indices('\n').map(|(idx, _)| idx + 1))
+                .collect::<Vec<_>>();
+            for (line, start) in line_starts.iter().enumerate() {
+                assert_eq!(rope.line_to_byte(line), *start);
+                assert_eq!(rope.byte_to_line(*start), line);
+            }
+            for (char_idx, (byte, _)) in expected.char_indices().enumerate().step_by(7) {
+                assert_eq!(rope.char_to_byte(char_idx), byte);
+                assert_eq!(rope.byte_to_char(byte), char_idx);
+                let line = line_starts.partition_point(|start| *start <= byte) - 1;
+                assert_eq!(rope.byte_to_line(byte), line);
+            }
+            assert_eq!(rope.byte_to_line(expected.len()), line_starts.len() - 1);
+            assert_eq!(rope.char_to_byte(rope.len_chars()), expected.len());
+
+            for (snapshot, text) in snapshots {
+                assert_eq!(snapshot.to_string(), text);
+            }
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "byte is not a char boundary")]
+    fn test_rope_char_boundary() {
+        let mut rope = Rope::from("héllo");
+        rope.insert(2, "x");
+    }
+
+    #[test]
+    #[should_panic(expected = "byte out of bounds")]
+    fn test_rope_range_end_overflow() {
+        let mut rope = Rope::from("hello");
+        rope.remove(..=usize::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "byte out of bounds")]
+    fn test_rope_range_start_overflow() {
+        let rope = Rope::from("hello");
+        rope.slice((Bound::Excluded(usize::MAX), Bound::Unbounded));
+    }
+
+    #[test]
+    fn test_tree_snapshot() {
+        let mut tree = BTree::<i32, i32>::new_with_config(BTreeConfig { max_degree: 4 });
+        for i in 0..50 {
+            tree.insert(i, i);
+        }
+
+        let mut snapshot = tree.snapshot();
+        assert!(Arc::ptr_eq(
+            tree.root.as_ref().unwrap(),
+            snapshot.root.as_ref().unwrap()
+        ));
+
+        // mutate the original, snapshot should not see it
+        tree.insert(100, 100);
+        {
+            // only the changed path is copied, the rest stays shared
+            let tree_root = tree.root.as_ref().unwrap();
+            let snapshot_root = snapshot.root.as_ref().unwrap();
+            assert!(!Arc::ptr_eq(tree_root, snapshot_root));
+            assert!(Arc::ptr_eq(
+                &tree_root.children[0],
+                &snapshot_root.children[0]
+            ));
+        }
+        tree.delete_by_key(&10);
+        assert_eq!(*tree.get_by_key(&100).unwrap(), 100);
+        assert!(tree.get_by_key(&10).is_none());
+        assert!(snapshot.get_by_key(&100).is_none());
+        assert_eq!(*snapshot.get_by_key(&10).unwrap(), 10);
+
+        // mutate the snapshot, original should not see it
+        snapshot.insert(200, 200);
+        snapshot.delete_by_key(&20);
+        assert_eq!(*snapshot.get_by_key(&200).unwrap(), 200);
+        assert!(snapshot.get_by_key(&20).is_none());
+        assert!(tree.get_by_key(&200).is_none());
+        assert_eq!(*tree.get_by_key(&20).unwrap(), 20);
     }
 }
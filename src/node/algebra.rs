@@ -0,0 +1,115 @@
+/// set algebra between two trees. The root of one tree is used to split the
+/// other, then pieces are processed recursively and joined back. Subtrees
+/// shared by both trees (`Arc::ptr_eq`) are reused or dropped as a whole.
+use super::join::Subtree;
+use super::split::SplitResult;
+use crate::{BTreeConfig, Comparator, Summary};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SetOp {
+    Union,
+    Intersection,
+    Difference,
+    SymmetricDifference,
+}
+
+impl SetOp {
+    fn keep_left_only(&self) -> bool {
+        matches!(
+            self,
+            SetOp::Union | SetOp::Difference | SetOp::SymmetricDifference
+        )
+    }
+
+    fn keep_right_only(&self) -> bool {
+        matches!(self, SetOp::Union | SetOp::SymmetricDifference)
+    }
+
+    fn keep_both(&self) -> bool {
+        matches!(self, SetOp::Union | SetOp::Intersection)
+    }
+}
+
+impl<K: Clone, V: Clone, S: Summary<K, V>> Subtree<K, V, S> {
+    /// apply op on left and right. For key in both trees, union keeps value
+    /// returned by resolve(key, left_value, right_value), intersection keeps
+    /// left's. Resolve is not called for shared subtrees, they are kept as is.
+    pub fn set_op<C: Comparator<K>>(
+        left: Option<Self>,
+        right: Option<Self>,
+        op: SetOp,
+        resolve: &mut impl FnMut(&K, &V, &V) -> V,
+        comparator: &C,
+        config: &BTreeConfig,
+    ) -> Option<Self> {
+        let (left, right) = match (left, right) {
+            (None, None) => return None,
+            (Some(left), None) => return left.keep_if(op.keep_left_only()),
+            (None, Some(right)) => return right.keep_if(op.keep_right_only()),
+            (Some(left), Some(right)) if Arc::ptr_eq(&left.node, &right.node) => {
+                return left.keep_if(op.keep_both());
+            }
+            (Some(left), Some(right)) => (left, right),
+        };
+
+        // split left by right root's keys, process each piece with the
+        // matching child of right root
+        let right_node = right.node;
+        let mut rest = Some(left);
+        let mut result = None;
+        let mut pending_key_value = None;
+
+        for idx in 0..=right_node.key_values.len() {
+            let (piece, matched) = match right_node.key_values.get(idx) {
+                Some((key, _)) => match rest.take() {
+                    Some(rest_tree) => {
+                        let SplitResult {
+                            left,
+                            matched,
+                            right,
+                        } = rest_tree.split(&mut |node| node.search(key, comparator), config);
+                        rest = right;
+                        (left, matched)
+                    }
+                    None => (None, None),
+                },
+                None => (rest.take(), None),
+            };
+
+            let child = right_node.children.get(idx).map(|child| Subtree {
+                node: child.clone(),
+                height: right.height - 1,
+            });
+            let piece = Self::set_op(piece, child, op, resolve, comparator, config);
+
+            result = match pending_key_value.take() {
+                Some(key_value) => Some(Self::join(result, key_value, piece, config)),
+                None => Self::concat(result, piece, config),
+            };
+
+            if let Some(right_key_value) = right_node.key_values.get(idx) {
+                pending_key_value = match matched {
+                    Some((key, value)) if op == SetOp::Union => {
+                        let value = resolve(&key, &value, &right_key_value.1);
+                        Some((key, value))
+                    }
+                    Some(left_key_value) if op.keep_both() => Some(left_key_value),
+                    Some(_) => None,
+                    None if op.keep_right_only() => Some(right_key_value.clone()),
+                    None => None,
+                };
+            }
+        }
+
+        result
+    }
+
+    fn keep_if(self, keep: bool) -> Option<Self> {
+        if keep {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,149 @@
+/// build tree bottom up from sorted key values in O(n)
+use super::node::Node;
+use crate::{BTreeConfig, Comparator, Summary};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortedIterError {
+    /// key at offset is less than the key before it
+    Unsorted { offset: usize },
+    /// key at offset equals the key before it
+    Duplicate { offset: usize },
+}
+
+impl std::fmt::Display for SortedIterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsorted { offset } => write!(f, "key at offset {offset} is not sorted"),
+            Self::Duplicate { offset } => write!(f, "key at offset {offset} is duplicated"),
+        }
+    }
+}
+
+impl std::error::Error for SortedIterError {}
+
+/// collect key values, check they are strictly ascending by comparator
+pub(crate) fn collect_sorted<K, V, C: Comparator<K>>(
+    iter: impl IntoIterator<Item = (K, V)>,
+    comparator: &C,
+) -> Result<Vec<(K, V)>, SortedIterError> {
+    let mut key_values: Vec<(K, V)> = vec![];
+    for (offset, (key, value)) in iter.into_iter().enumerate() {
+        if let Some((prev_key, _)) = key_values.last() {
+            match comparator.cmp(prev_key, &key) {
+                std::cmp::Ordering::Less => {}
+                std::cmp::Ordering::Equal => return Err(SortedIterError::Duplicate { offset }),
+                std::cmp::Ordering::Greater => return Err(SortedIterError::Unsorted { offset }),
+            }
+        }
+        key_values.push((key, value));
+    }
+    Ok(key_values)
+}
+
+impl<K: Clone, V: Clone, S: Summary<K, V>> Node<K, V, S> {
+    /// build a tree from sorted key values, None if empty
+    pub(crate) fn build(key_values: Vec<(K, V)>, config: &BTreeConfig) -> Option<Self> {
+        let n = key_values.len();
+        if n == 0 {
+            return None;
+        }
+
+        // lowest height which can hold all key values
+        let mut height = 0;
+        while max_size(height, config) < n {
+            height += 1;
+        }
+
+        let mut iter = key_values.into_iter();
+        Some(Self::build_inner(&mut iter, n, height, true, config))
+    }
+
+    /// take n key values from iter, build a node of height. Non-root nodes'
+    /// size is always within [min_size, max_size] of the height.
+    fn build_inner(
+        iter: &mut impl Iterator<Item = (K, V)>,
+        n: usize,
+        height: usize,
+        is_root: bool,
+        config: &BTreeConfig,
+    ) -> Self {
+        if height == 0 {
+            return Self::new_with_key_values(iter.take(n).collect(), vec![]);
+        }
+
+        // each child together with the key value after it holds
+        // [min_size + 1, max_size + 1] key values
+        let child_max = max_size(height - 1, config) + 1;
+        let min_children = if is_root {
+            2
+        } else {
+            config.node_min_children()
+        };
+        let child_n = std::cmp::max((n + 1).div_ceil(child_max), min_children);
+
+        let total = n + 1 - child_n;
+        let (base, extra) = (total / child_n, total % child_n);
+
+        let mut key_values = Vec::with_capacity(child_n - 1);
+        let mut children = Vec::with_capacity(child_n);
+        for idx in 0..child_n {
+            let size = if idx < extra { base + 1 } else { base };
+            children.push(Arc::new(Self::build_inner(
+                iter,
+                size,
+                height - 1,
+                false,
+                config,
+            )));
+            if idx + 1 < child_n {
+                key_values.push(iter.next().unwrap());
+            }
+        }
+
+        Self::new_with_key_values(key_values, children)
+    }
+}
+
+/// max key values a subtree of height can hold
+fn max_size(height: usize, config: &BTreeConfig) -> usize {
+    let mut size = config.node_max_key_value();
+    for _ in 0..height {
+        size = size
+            .saturating_mul(config.node_max_children())
+            .saturating_add(config.node_max_key_value());
+    }
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OrdComparator;
+
+    #[test]
+    fn test_node_build() {
+        for max_degree in 3..10 {
+            let config = BTreeConfig { max_degree };
+            for n in 1..400 {
+                let node =
+                    Node::<i32, i32>::build((0..n).map(|i| (i, i)).collect(), &config).unwrap();
+                node.check_invariants(&config);
+                assert_eq!(node.count, n as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn test_collect_sorted() {
+        assert!(collect_sorted([(1, ()), (2, ()), (3, ())], &OrdComparator).is_ok());
+        assert_eq!(
+            collect_sorted([(1, ()), (3, ()), (2, ())], &OrdComparator).unwrap_err(),
+            SortedIterError::Unsorted { offset: 2 }
+        );
+        assert_eq!(
+            collect_sorted([(1, ()), (1, ())], &OrdComparator).unwrap_err(),
+            SortedIterError::Duplicate { offset: 1 }
+        );
+    }
+}
@@ -1,51 +1,141 @@
 use super::node::Node;
-use crate::BTreeConfig;
+use crate::{BTreeConfig, Comparator, Summary};
+use std::borrow::Borrow;
 use std::sync::Arc;
 
-impl<K: Ord + Clone, V: Clone> Node<K, V> {
-    pub fn delete_by_key(&mut self, key: &K, config: &BTreeConfig) -> Option<(K, V)> {
+impl<K: Clone, V: Clone, S: Summary<K, V>> Node<K, V, S> {
+    pub fn delete_by_key<Q: ?Sized, C: Comparator<Q>>(
+        &mut self,
+        key: &Q,
+        comparator: &C,
+        config: &BTreeConfig,
+    ) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+    {
+        match self.search(key, comparator) {
+            Ok(idx) => Some(self.delete_at(idx, config)),
+            Err(_) if self.is_leaf() => None,
+            Err(idx) => {
+                let child = Arc::make_mut(&mut self.children[idx]);
+                let deleted_k_v = child.delete_by_key(key, comparator, config)?;
+                self.count -= 1;
+                self.refresh_summary();
+                self.rebalance(idx, config);
+                Some(deleted_k_v)
+            }
+        }
+    }
+
+    /// delete key value at idx of the node located by path, path is the child
+    /// indexes walked from this node
+    pub fn delete_at_path(&mut self, path: &[usize], idx: usize, config: &BTreeConfig) -> (K, V) {
+        match path.split_first() {
+            None => self.delete_at(idx, config),
+            Some((&child_idx, path)) => {
+                let child = Arc::make_mut(&mut self.children[child_idx]);
+                let deleted_k_v = child.delete_at_path(path, idx, config);
+                self.count -= 1;
+                self.refresh_summary();
+                self.rebalance(child_idx, config);
+                deleted_k_v
+            }
+        }
+    }
+
+    /// delete key value at offset, descend by children's count
+    pub fn delete_by_offset(&mut self, offset: usize, config: &BTreeConfig) -> Option<(K, V)> {
+        if self.count <= offset {
+            return None;
+        }
+
         if self.is_leaf() {
-            match self.key_values.binary_search_by(|(k, _)| k.cmp(key)) {
-                Ok(idx) => {
-                    self.count -= 1;
-                    Some(self.key_values.remove(idx))
-                }
-                Err(_) => None,
+            return Some(self.delete_at(offset, config));
+        }
+
+        let mut relative_offset = offset;
+        let mut child_idx = self.children.len() - 1;
+
+        for idx in 0..self.key_values.len() {
+            let left_child_count = self.children[idx].count;
+            if left_child_count > relative_offset {
+                child_idx = idx;
+                break;
             }
-        } else {
-            match self.key_values.binary_search_by(|(k, _)| k.cmp(key)) {
-                Ok(idx) => {
-                    // find the left most large key, replace it here
-                    let child = Arc::make_mut(&mut self.children[idx]);
-                    let left_most_large_key = child.take_right_most(config);
-                    let prev_key_value =
-                        std::mem::replace(&mut self.key_values[idx], left_most_large_key);
-
-                    self.rebalance(idx, config);
-
-                    Some(prev_key_value)
-                }
-                Err(idx) => {
-                    let child = Arc::make_mut(&mut self.children[idx]);
-                    let deleted_k_v = child.delete_by_key(key, config)?;
-                    self.count -= 1;
-                    self.rebalance(idx, config);
-                    Some(deleted_k_v)
-                }
+
+            relative_offset -= left_child_count;
+
+            if relative_offset == 0 {
+                return Some(self.delete_at(idx, config));
             }
+
+            relative_offset -= 1;
+        }
+
+        let child = Arc::make_mut(&mut self.children[child_idx]);
+        let deleted_k_v = child.delete_by_offset(relative_offset, config);
+        self.count -= 1;
+        self.refresh_summary();
+        self.rebalance(child_idx, config);
+        deleted_k_v
+    }
+
+    /// delete key value at idx of this node
+    fn delete_at(&mut self, idx: usize, config: &BTreeConfig) -> (K, V) {
+        if self.is_leaf() {
+            let key_value = self.key_values.remove(idx);
+            self.count -= 1;
+            self.refresh_summary();
+            return key_value;
+        }
+
+        // find the left most large key, replace it here
+        let child = Arc::make_mut(&mut self.children[idx]);
+        let left_most_large_key = child.take_right_most(config);
+        let prev_key_value = std::mem::replace(&mut self.key_values[idx], left_most_large_key);
+        self.count -= 1;
+        self.refresh_summary();
+
+        self.rebalance(idx, config);
+
+        prev_key_value
+    }
+
+    /// remove the first key value under this node
+    pub(crate) fn take_left_most(&mut self, config: &BTreeConfig) -> (K, V) {
+        if self.is_leaf() {
+            // shrink is processed at parent. At leaf, just delete and return
+            let key_value = self.key_values.remove(0);
+            self.count -= 1;
+            self.refresh_summary();
+            return key_value;
         }
+
+        let left_most_child = Arc::make_mut(&mut self.children[0]);
+        let left_most = left_most_child.take_left_most(config);
+        self.count -= 1;
+        self.refresh_summary();
+
+        self.rebalance(0, config);
+
+        left_most
     }
 
-    fn take_right_most(&mut self, config: &BTreeConfig) -> (K, V) {
+    /// remove the last key value under this node
+    pub(crate) fn take_right_most(&mut self, config: &BTreeConfig) -> (K, V) {
         if self.is_leaf() {
             // shrink is processed at parent. At leaf, just delete and return
+            let key_value = self.key_values.pop().unwrap();
             self.count -= 1;
-            return self.key_values.pop().unwrap();
+            self.refresh_summary();
+            return key_value;
         }
 
         let child_idx = self.children.len() - 1;
         let right_most_child = Arc::make_mut(self.children.last_mut().unwrap());
         let right_most = right_most_child.take_right_most(config);
+        self.count -= 1;
+        self.refresh_summary();
 
         self.rebalance(child_idx, config);
 
@@ -68,6 +158,8 @@ impl<K: Ord + Clone, V: Clone> Node<K, V> {
 
         if config.node_at_min_size(left_child.key_values.len())
             && config.node_at_min_size(right_child.key_values.len())
+            && !config
+                .node_should_split(left_child.key_values.len() + right_child.key_values.len() + 1)
         {
             // merge two children
             let mut new_child_key_values = left_child.key_values.clone();
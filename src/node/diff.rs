@@ -0,0 +1,180 @@
+/// diff two trees in key order. Each side is a stack of pending tokens, a
+/// token is a whole subtree or a key value. Subtrees are only expanded when
+/// needed, and skipped when both sides have the same `Arc`.
+use super::node::Node;
+use crate::{Comparator, OrdComparator};
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffItem<'a, K, V> {
+    /// key value only in new tree
+    Added(&'a K, &'a V),
+    /// key value only in old tree
+    Removed(&'a K, &'a V),
+    /// key in both trees with different value, (key, old value, new value)
+    Changed(&'a K, &'a V, &'a V),
+}
+
+impl<'a, K, V> DiffItem<'a, K, V> {
+    pub fn key(&self) -> &'a K {
+        match self {
+            DiffItem::Added(k, _) | DiffItem::Removed(k, _) | DiffItem::Changed(k, _, _) => k,
+        }
+    }
+
+    /// value in old tree, None if added
+    pub fn old_value(&self) -> Option<&'a V> {
+        match self {
+            DiffItem::Added(..) => None,
+            DiffItem::Removed(_, v) | DiffItem::Changed(_, v, _) => Some(v),
+        }
+    }
+
+    /// value in new tree, None if removed
+    pub fn new_value(&self) -> Option<&'a V> {
+        match self {
+            DiffItem::Removed(..) => None,
+            DiffItem::Added(_, v) | DiffItem::Changed(_, _, v) => Some(v),
+        }
+    }
+}
+
+/// a key changed differently by both sides of a three way merge, value is
+/// None if key not exists in that version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergeConflict<'a, K, V> {
+    pub key: &'a K,
+    pub base: Option<&'a V>,
+    pub ours: Option<&'a V>,
+    pub theirs: Option<&'a V>,
+}
+
+enum Token<'a, K, V, S> {
+    /// subtree and its height
+    Node(&'a Arc<Node<K, V, S>>, usize),
+    KeyValue(&'a (K, V)),
+}
+
+pub struct Diff<'a, K, V, C = OrdComparator, S = ()> {
+    old: Vec<Token<'a, K, V, S>>,
+    new: Vec<Token<'a, K, V, S>>,
+    comparator: &'a C,
+}
+
+impl<'a, K, V, C, S> Diff<'a, K, V, C, S> {
+    pub(crate) fn new(
+        old: Option<&'a Arc<Node<K, V, S>>>,
+        new: Option<&'a Arc<Node<K, V, S>>>,
+        comparator: &'a C,
+    ) -> Self {
+        let tokens = |root: Option<&'a Arc<Node<K, V, S>>>| {
+            root.map(|root| Token::Node(root, root.height()))
+                .into_iter()
+                .collect()
+        };
+        Self {
+            old: tokens(old),
+            new: tokens(new),
+            comparator,
+        }
+    }
+}
+
+/// replace subtree token on top with its children and key values
+fn expand<K, V, S>(tokens: &mut Vec<Token<'_, K, V, S>>) {
+    let (node, height) = match tokens.pop() {
+        Some(Token::Node(node, height)) => (node, height),
+        _ => unreachable!(),
+    };
+
+    // push in reverse order, so the left most is on top
+    if let Some(last_child) = node.children.last() {
+        tokens.push(Token::Node(last_child, height - 1));
+    }
+    for idx in (0..node.key_values.len()).rev() {
+        tokens.push(Token::KeyValue(&node.key_values[idx]));
+        if !node.is_leaf() {
+            tokens.push(Token::Node(&node.children[idx], height - 1));
+        }
+    }
+}
+
+fn first_key<K, V, S>(node: &Node<K, V, S>) -> &K {
+    let mut node = node;
+    while !node.is_leaf() {
+        node = &node.children[0];
+    }
+    &node.key_values[0].0
+}
+
+impl<'a, K, V: PartialEq, C: Comparator<K>, S> Iterator for Diff<'a, K, V, C, S> {
+    type Item = DiffItem<'a, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match (self.old.last(), self.new.last()) {
+                (None, None) => return None,
+                (Some(Token::KeyValue((k, v))), None) => {
+                    self.old.pop();
+                    return Some(DiffItem::Removed(k, v));
+                }
+                (None, Some(Token::KeyValue((k, v)))) => {
+                    self.new.pop();
+                    return Some(DiffItem::Added(k, v));
+                }
+                (Some(Token::Node(..)), None) => expand(&mut self.old),
+                (None, Some(Token::Node(..))) => expand(&mut self.new),
+                (Some(Token::Node(old, old_height)), Some(Token::Node(new, new_height))) => {
+                    if Arc::ptr_eq(old, new) {
+                        self.old.pop();
+                        self.new.pop();
+                        continue;
+                    }
+
+                    // expand the higher one first, so shared subtrees line up
+                    let (old_height, new_height) = (*old_height, *new_height);
+                    if old_height >= new_height {
+                        expand(&mut self.old);
+                    }
+                    if new_height >= old_height {
+                        expand(&mut self.new);
+                    }
+                }
+                (Some(Token::Node(old, _)), Some(Token::KeyValue((k, v)))) => {
+                    if self.comparator.cmp(k, first_key(old)) == Ordering::Less {
+                        self.new.pop();
+                        return Some(DiffItem::Added(k, v));
+                    }
+                    expand(&mut self.old);
+                }
+                (Some(Token::KeyValue((k, v))), Some(Token::Node(new, _))) => {
+                    if self.comparator.cmp(k, first_key(new)) == Ordering::Less {
+                        self.old.pop();
+                        return Some(DiffItem::Removed(k, v));
+                    }
+                    expand(&mut self.new);
+                }
+                (Some(Token::KeyValue((old_k, old_v))), Some(Token::KeyValue((new_k, new_v)))) => {
+                    match self.comparator.cmp(old_k, new_k) {
+                        Ordering::Less => {
+                            self.old.pop();
+                            return Some(DiffItem::Removed(old_k, old_v));
+                        }
+                        Ordering::Greater => {
+                            self.new.pop();
+                            return Some(DiffItem::Added(new_k, new_v));
+                        }
+                        Ordering::Equal => {
+                            self.old.pop();
+                            self.new.pop();
+                            if old_v != new_v {
+                                return Some(DiffItem::Changed(old_k, old_v, new_v));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
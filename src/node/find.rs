@@ -39,6 +39,29 @@ impl<K: Ord> KeyRangeResult<'_, K> {
             },
         }
     }
+}
+
+impl<K> KeyRangeResult<'_, K> {
+    /// merge with other, which must follow self in tree order. Start is
+    /// taken from the left most part and end from the right most part, so
+    /// keys are never compared and any comparator's order is kept.
+    #[must_use]
+    pub(crate) fn concat_in_order(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::None, other) => other,
+            (this, Self::None) => this,
+            (
+                Self::Some {
+                    start, n: self_n, ..
+                },
+                Self::Some { end, n, .. },
+            ) => Self::Some {
+                start,
+                end,
+                n: self_n + n,
+            },
+        }
+    }
 
     pub fn n(&self) -> usize {
         match self {
@@ -50,14 +73,14 @@ impl<K: Ord> KeyRangeResult<'_, K> {
     pub fn start_key(&self) -> Option<&K> {
         match self {
             KeyRangeResult::None => None,
-            KeyRangeResult::Some { ref start, .. } => Some(start),
+            KeyRangeResult::Some { start, .. } => Some(start),
         }
     }
 
     pub fn end_key(&self) -> Option<&K> {
         match self {
             KeyRangeResult::None => None,
-            KeyRangeResult::Some { ref end, .. } => Some(end),
+            KeyRangeResult::Some { end, .. } => Some(end),
         }
     }
 }
@@ -69,12 +92,15 @@ pub enum PredicateResult {
     Right,
 }
 
-impl<K: Ord + Clone, V: Clone> Node<K, V> {
+impl<K: Clone, V: Clone, S> Node<K, V, S> {
     /// predicate result should be consistent for range
     /// if true for smaller range, then it must be true for larger range
     /// if false for larger range, then it must be false for smaller range
     /// this helps us to visit range with logn
-    pub fn find_key_range<P: Fn(&K) -> PredicateResult>(&self, predicate: &P) -> KeyRangeResult<K> {
+    pub fn find_key_range<P: Fn(&K) -> PredicateResult>(
+        &self,
+        predicate: &P,
+    ) -> KeyRangeResult<'_, K> {
         if self.is_leaf() {
             let mut key_predicate_iter = self
                 .key_values
@@ -87,7 +113,7 @@ impl<K: Ord + Clone, V: Clone> Node<K, V> {
                     let mut end_key: &K = start_key;
                     let mut count = 1;
 
-                    while let Some(key) = key_predicate_iter.next() {
+                    for key in key_predicate_iter {
                         count += 1;
                         end_key = &key.0;
                     }
@@ -100,71 +126,44 @@ impl<K: Ord + Clone, V: Clone> Node<K, V> {
                 }
             }
         } else {
-            // for branch,
-            // 1. find the first idx with predicate as Equal/Greater, it is the start
-            // 2. then find the first idx with predicate as Greater, also need to check last
-            //     child
-
-            let mut result = KeyRangeResult::None;
-            let mut extra_child_to_check: Option<usize> = None;
-
+            // for branch, key values matching the predicate are contiguous:
+            // visit the child before the first match, count everything
+            // between the first and the last match, then visit the child
+            // after the last match. Results are merged in tree order.
             let mut matched_indexes = vec![];
+            let mut tail_child = self.key_values.len();
 
             for (index, key) in self.key_values.iter().enumerate() {
                 match predicate(&key.0) {
-                    PredicateResult::Left => {
-                        extra_child_to_check = Some(index + 1);
-                        continue;
-                    }
-                    PredicateResult::Match => {
-                        matched_indexes.push((index, key));
-                        extra_child_to_check = Some(index + 1);
-                    }
+                    PredicateResult::Left => continue,
+                    PredicateResult::Match => matched_indexes.push((index, key)),
                     PredicateResult::Right => {
-                        extra_child_to_check = None;
-                        result = result.merge_into(self.children[index].find_key_range(predicate));
+                        tail_child = index;
                         break;
                     }
                 }
             }
 
-            if !matched_indexes.is_empty() {
-                let (first_idx, first_key) = matched_indexes[0];
+            let mut result = KeyRangeResult::None;
+            if let (Some(&(first_idx, first_key)), Some(&(last_idx, last_key))) =
+                (matched_indexes.first(), matched_indexes.last())
+            {
+                result = self.children[first_idx].find_key_range(predicate);
 
-                // for first match index, visit child to update result
-                result = result.merge_into(self.children[first_idx].find_key_range(predicate));
-                result = result.merge_into(KeyRangeResult::Some {
+                // we do not need to visit children between two matched keys
+                let mut count = 1;
+                for idx in first_idx + 1..=last_idx {
+                    // add children count, also the key value after it
+                    count += self.children[idx].count + 1;
+                }
+                result = result.concat_in_order(KeyRangeResult::Some {
                     start: &first_key.0,
-                    end: &first_key.0,
-                    n: 1,
+                    end: &last_key.0,
+                    n: count,
                 });
-
-                if matched_indexes.len() >= 2 {
-                    // we do not need to visit children between two matched key
-                    let (last_idx, last_key) = matched_indexes[matched_indexes.len() - 1];
-
-                    let mut count = 0;
-                    for idx in first_idx + 1..=last_idx {
-                        // add children count
-                        count += self.children[idx].count;
-                        // also add the item itself
-                        count += 1;
-                    }
-
-                    result = result.merge_into(KeyRangeResult::Some {
-                        start: &first_key.0,
-                        end: &last_key.0,
-                        n: count,
-                    });
-                } else {
-                }
             }
 
-            if let Some(child_idx) = extra_child_to_check {
-                result = result.merge_into(self.children[child_idx].find_key_range(predicate));
-            }
-
-            result
+            result.concat_in_order(self.children[tail_child].find_key_range(predicate))
         }
     }
 }
@@ -230,4 +229,31 @@ mod tests {
             assert_eq!(*find_result.end_key().unwrap(), 40);
         }
     }
+
+    #[test]
+    fn test_key_range_merge() {
+        let (a, b, c, d) = (1, 2, 3, 4);
+        let left = || KeyRangeResult::Some {
+            start: &a,
+            end: &b,
+            n: 2,
+        };
+        let right = || KeyRangeResult::Some {
+            start: &c,
+            end: &d,
+            n: 2,
+        };
+
+        // merge_into takes the smallest start and the largest end either way
+        for merged in [left().merge_into(right()), right().merge_into(left())] {
+            assert_eq!((merged.start_key(), merged.end_key()), (Some(&1), Some(&4)));
+            assert_eq!(merged.n(), 4);
+        }
+
+        // concat_in_order trusts the argument order instead of comparing
+        let merged = right().concat_in_order(left());
+        assert_eq!((merged.start_key(), merged.end_key()), (Some(&3), Some(&2)));
+        assert_eq!(merged.n(), 4);
+        assert_eq!(KeyRangeResult::None.concat_in_order(left()).n(), 2);
+    }
 }
@@ -1,25 +1,56 @@
 use super::node::Node;
-use crate::BTreeConfig;
+use crate::{BTreeConfig, Comparator, Summary};
 use std::sync::Arc;
 
-pub enum InsertResult<K, V> {
+pub enum InsertResult<K, V, S = ()> {
     Splited {
         new_k_v: (K, V),
-        new_l: Arc<Node<K, V>>,
-        new_r: Arc<Node<K, V>>,
+        new_l: Arc<Node<K, V, S>>,
+        new_r: Arc<Node<K, V, S>>,
     },
     NotSplited {
         is_new: bool,
     },
 }
 
-impl<K: Ord + Clone, V: Clone> Node<K, V> {
-    pub fn insert(&mut self, key: K, value: V, config: &BTreeConfig) -> InsertResult<K, V> {
+/// child indexes and idx of a key value, relative to some node
+pub(crate) type Slot = (Vec<usize>, usize);
+
+/// slot relative to the parent, after the child at child_idx splitted into
+/// a left node of left_len key values, the middle key value moved up into
+/// the parent at child_idx, and the right node
+pub(crate) fn slot_after_split(child_idx: usize, left_len: usize, slot: Slot) -> Slot {
+    let (mut path, idx) = slot;
+    match path.first_mut() {
+        None if idx < left_len => (vec![child_idx], idx),
+        None if idx == left_len => (vec![], child_idx),
+        None => (vec![child_idx + 1], idx - left_len - 1),
+        Some(grand_child_idx) if *grand_child_idx <= left_len => {
+            path.insert(0, child_idx);
+            (path, idx)
+        }
+        Some(grand_child_idx) => {
+            *grand_child_idx -= left_len + 1;
+            path.insert(0, child_idx + 1);
+            (path, idx)
+        }
+    }
+}
+
+impl<K: Clone, V: Clone, S: Summary<K, V>> Node<K, V, S> {
+    pub fn insert<C: Comparator<K>>(
+        &mut self,
+        key: K,
+        value: V,
+        comparator: &C,
+        config: &BTreeConfig,
+    ) -> InsertResult<K, V, S> {
         let is_new = if self.is_leaf() {
-            match self.key_values.binary_search_by(|(k, _)| k.cmp(&key)) {
+            match self.search(&key, comparator) {
                 Ok(idx) => {
                     // we are the node
                     self.key_values[idx] = (key, value);
+                    self.refresh_summary();
                     return InsertResult::NotSplited { is_new: false };
                 }
                 Err(idx) => {
@@ -29,52 +60,161 @@ impl<K: Ord + Clone, V: Clone> Node<K, V> {
                 }
             }
         } else {
-            match self.key_values.binary_search_by(|(k, _v)| k.cmp(&key)) {
+            match self.search(&key, comparator) {
                 Ok(idx) => {
                     // we are the node
                     self.key_values[idx] = (key, value);
+                    self.refresh_summary();
                     return InsertResult::NotSplited { is_new: false };
                 }
                 Err(idx) => {
                     // we should insert at child at idx
                     let child = Arc::make_mut(&mut self.children[idx]);
-                    match child.insert(key, value, config) {
-                        InsertResult::NotSplited { is_new } => {
-                            if is_new {
-                                self.count += 1;
-                            }
-                            return InsertResult::NotSplited { is_new };
-                        }
-                        InsertResult::Splited {
-                            new_k_v,
-                            new_l,
-                            new_r,
-                        } => {
-                            self.count += 1;
-                            self.key_values.insert(idx, new_k_v);
-                            self.children[idx] = new_l;
-                            self.children.insert(idx + 1, new_r);
-                            true
-                        }
-                    }
+                    let child_result = child.insert(key, value, comparator, config);
+                    return self.merge_child_insert_result(idx, child_result, config);
                 }
             }
         };
 
+        self.split_if_overflow(is_new, config)
+    }
+
+    /// insert key value at idx of the node located by path, path is the child
+    /// indexes walked from this node. Caller should make sure key is not in
+    /// tree and idx keeps key values ordered.
+    pub fn insert_at_path(
+        &mut self,
+        path: &[usize],
+        idx: usize,
+        key: K,
+        value: V,
+        config: &BTreeConfig,
+    ) -> InsertResult<K, V, S> {
+        self.insert_at_path_with_slot(path, idx, key, value, config)
+            .0
+    }
+
+    /// same as `insert_at_path`, also returns the slot of the inserted key
+    /// value relative to this node, so callers can reach it without another
+    /// search. If this node splitted, the slot is relative to it before the
+    /// split, see `slot_after_split`.
+    pub(crate) fn insert_at_path_with_slot(
+        &mut self,
+        path: &[usize],
+        idx: usize,
+        key: K,
+        value: V,
+        config: &BTreeConfig,
+    ) -> (InsertResult<K, V, S>, Slot) {
+        match path.split_first() {
+            None => {
+                self.key_values.insert(idx, (key, value));
+                self.count += 1;
+                (self.split_if_overflow(true, config), (vec![], idx))
+            }
+            Some((&child_idx, path)) => {
+                let child = Arc::make_mut(&mut self.children[child_idx]);
+                let (child_result, (mut child_path, idx)) =
+                    child.insert_at_path_with_slot(path, idx, key, value, config);
+                let slot = match &child_result {
+                    InsertResult::Splited { new_l, .. } => {
+                        slot_after_split(child_idx, new_l.key_values.len(), (child_path, idx))
+                    }
+                    InsertResult::NotSplited { .. } => {
+                        child_path.insert(0, child_idx);
+                        (child_path, idx)
+                    }
+                };
+                (
+                    self.merge_child_insert_result(child_idx, child_result, config),
+                    slot,
+                )
+            }
+        }
+    }
+
+    /// insert key value so that it lands at offset, descend by children's
+    /// count. Caller should make sure offset is within [0, count] and keeps
+    /// key values ordered.
+    pub fn insert_at_offset(
+        &mut self,
+        offset: usize,
+        key: K,
+        value: V,
+        config: &BTreeConfig,
+    ) -> InsertResult<K, V, S> {
+        if self.is_leaf() {
+            self.key_values.insert(offset, (key, value));
+            self.count += 1;
+            return self.split_if_overflow(true, config);
+        }
+
+        let mut relative_offset = offset;
+        let mut child_idx = self.children.len() - 1;
+        for idx in 0..self.key_values.len() {
+            let left_child_count = self.children[idx].count;
+            if left_child_count >= relative_offset {
+                child_idx = idx;
+                break;
+            }
+            relative_offset -= left_child_count + 1;
+        }
+
+        let child = Arc::make_mut(&mut self.children[child_idx]);
+        let child_result = child.insert_at_offset(relative_offset, key, value, config);
+        self.merge_child_insert_result(child_idx, child_result, config)
+    }
+
+    /// update self after child at child_idx inserted, take the key value and
+    /// new children if child splitted
+    fn merge_child_insert_result(
+        &mut self,
+        child_idx: usize,
+        child_result: InsertResult<K, V, S>,
+        config: &BTreeConfig,
+    ) -> InsertResult<K, V, S> {
+        match child_result {
+            InsertResult::NotSplited { is_new } => {
+                if is_new {
+                    self.count += 1;
+                }
+                self.refresh_summary();
+                InsertResult::NotSplited { is_new }
+            }
+            InsertResult::Splited {
+                new_k_v,
+                new_l,
+                new_r,
+            } => {
+                self.count += 1;
+                self.key_values.insert(child_idx, new_k_v);
+                self.children[child_idx] = new_l;
+                self.children.insert(child_idx + 1, new_r);
+                self.split_if_overflow(true, config)
+            }
+        }
+    }
+
+    pub(crate) fn split_if_overflow(
+        &mut self,
+        is_new: bool,
+        config: &BTreeConfig,
+    ) -> InsertResult<K, V, S> {
         if !config.node_should_split(self.key_values.len()) {
+            self.refresh_summary();
             return InsertResult::NotSplited { is_new };
         }
 
         let split_at = self.key_values.len() / 2;
         let split_off = split_at + 1;
 
-        let mut left_key_values = std::mem::replace(&mut self.key_values, vec![]);
+        let mut left_key_values = std::mem::take(&mut self.key_values);
 
         let right_key_values = left_key_values.split_off(split_off);
         let root_key_value = left_key_values.pop().unwrap();
 
         let (left_children, right_children) = if !self.is_leaf() {
-            let mut left_children = std::mem::replace(&mut self.children, vec![]);
+            let mut left_children = std::mem::take(&mut self.children);
             let right_children = left_children.split_off(split_off);
             (left_children, right_children)
         } else {
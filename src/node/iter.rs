@@ -0,0 +1,294 @@
+/// in order iteration over node
+///
+/// Each end of the iteration keeps a stack of (node, index) from root to the
+/// node holding the next key value. For the front stack, index is the next
+/// key value to yield; for the back stack, index is one past it. Both ends
+/// walk independently, `remaining` tells when they meet.
+use super::node::Node;
+
+pub struct Iter<'a, K, V, S = ()> {
+    front: Vec<(&'a Node<K, V, S>, usize)>,
+    back: Vec<(&'a Node<K, V, S>, usize)>,
+    remaining: usize,
+}
+
+impl<'a, K, V, S> Iter<'a, K, V, S> {
+    /// iterate key values in [start, end) offset range, offsets must be
+    /// within root's count
+    pub(crate) fn new(root: Option<&'a Node<K, V, S>>, start: usize, end: usize) -> Self {
+        let mut iter = Self {
+            front: vec![],
+            back: vec![],
+            remaining: end.saturating_sub(start),
+        };
+
+        if let Some(root) = root {
+            if iter.remaining > 0 {
+                seek_front(&mut iter.front, root, start);
+                seek_back(&mut iter.back, root, end - 1);
+            }
+        }
+
+        iter
+    }
+}
+
+/// build front stack so that the next key value yielded is the one at offset
+pub(crate) fn seek_front<'a, K, V, S>(
+    stack: &mut Vec<(&'a Node<K, V, S>, usize)>,
+    root: &'a Node<K, V, S>,
+    offset: usize,
+) {
+    let mut node = root;
+    let mut relative_offset = offset;
+
+    'descend: loop {
+        if node.is_leaf() {
+            stack.push((node, relative_offset));
+            return;
+        }
+
+        for idx in 0..node.key_values.len() {
+            let left_child = &node.children[idx];
+            if left_child.count > relative_offset {
+                stack.push((node, idx));
+                node = left_child;
+                continue 'descend;
+            }
+
+            relative_offset -= left_child.count;
+
+            if relative_offset == 0 {
+                stack.push((node, idx));
+                return;
+            }
+
+            relative_offset -= 1;
+        }
+
+        stack.push((node, node.key_values.len()));
+        node = node.children.last().unwrap();
+    }
+}
+
+/// build back stack so that the next key value yielded is the one at offset
+fn seek_back<'a, K, V, S>(
+    stack: &mut Vec<(&'a Node<K, V, S>, usize)>,
+    root: &'a Node<K, V, S>,
+    offset: usize,
+) {
+    let mut node = root;
+    let mut relative_offset = offset;
+
+    'descend: loop {
+        if node.is_leaf() {
+            stack.push((node, relative_offset + 1));
+            return;
+        }
+
+        for idx in 0..node.key_values.len() {
+            let left_child = &node.children[idx];
+            if left_child.count > relative_offset {
+                stack.push((node, idx));
+                node = left_child;
+                continue 'descend;
+            }
+
+            relative_offset -= left_child.count;
+
+            if relative_offset == 0 {
+                stack.push((node, idx + 1));
+                return;
+            }
+
+            relative_offset -= 1;
+        }
+
+        stack.push((node, node.key_values.len()));
+        node = node.children.last().unwrap();
+    }
+}
+
+fn next_front<'a, K, V, S>(stack: &mut Vec<(&'a Node<K, V, S>, usize)>) -> Option<&'a (K, V)> {
+    loop {
+        let (node, idx) = *stack.last()?;
+        if idx < node.key_values.len() {
+            stack.last_mut().unwrap().1 += 1;
+
+            if !node.is_leaf() {
+                // push the left most path of the right child
+                let mut child: &Node<K, V, S> = &node.children[idx + 1];
+                loop {
+                    stack.push((child, 0));
+                    if child.is_leaf() {
+                        break;
+                    }
+                    child = &child.children[0];
+                }
+            }
+
+            return Some(&node.key_values[idx]);
+        }
+        stack.pop();
+    }
+}
+
+fn next_back<'a, K, V, S>(stack: &mut Vec<(&'a Node<K, V, S>, usize)>) -> Option<&'a (K, V)> {
+    loop {
+        let (node, end) = *stack.last()?;
+        if end > 0 {
+            let idx = end - 1;
+            stack.last_mut().unwrap().1 = idx;
+
+            if !node.is_leaf() {
+                // push the right most path of the left child
+                let mut child: &Node<K, V, S> = &node.children[idx];
+                loop {
+                    stack.push((child, child.key_values.len()));
+                    if child.is_leaf() {
+                        break;
+                    }
+                    child = child.children.last().unwrap();
+                }
+            }
+
+            return Some(&node.key_values[idx]);
+        }
+        stack.pop();
+    }
+}
+
+impl<'a, K, V, S> Iterator for Iter<'a, K, V, S> {
+    type Item = &'a (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        next_front(&mut self.front)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, S> DoubleEndedIterator for Iter<'_, K, V, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        next_back(&mut self.back)
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for Iter<'_, K, V, S> {}
+
+impl<K, V, S> std::iter::FusedIterator for Iter<'_, K, V, S> {}
+
+pub struct Keys<'a, K, V, S = ()> {
+    pub(crate) inner: Iter<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Iterator for Keys<'a, K, V, S> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, S> DoubleEndedIterator for Keys<'_, K, V, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for Keys<'_, K, V, S> {}
+
+impl<K, V, S> std::iter::FusedIterator for Keys<'_, K, V, S> {}
+
+pub struct Values<'a, K, V, S = ()> {
+    pub(crate) inner: Iter<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Iterator for Values<'a, K, V, S> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, S> DoubleEndedIterator for Values<'_, K, V, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, v)| v)
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for Values<'_, K, V, S> {}
+
+impl<K, V, S> std::iter::FusedIterator for Values<'_, K, V, S> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{node::insert::InsertResult, BTreeConfig, OrdComparator};
+
+    #[test]
+    fn test_node_iter() {
+        let config = BTreeConfig { max_degree: 4 };
+        let mut node = Node::<i32, i32>::new();
+        for i in (0..100i32).rev() {
+            if let InsertResult::Splited {
+                new_k_v,
+                new_l,
+                new_r,
+            } = node.insert(i, i, &OrdComparator, &config)
+            {
+                node = Node::new_with_key_values(vec![new_k_v], vec![new_l, new_r]);
+            }
+        }
+
+        let keys = Iter::new(Some(&node), 0, 100)
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, (0..100).collect::<Vec<_>>());
+
+        let keys = Iter::new(Some(&node), 0, 100)
+            .rev()
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, (0..100).rev().collect::<Vec<_>>());
+
+        // every sub range, walk from both ends until they meet
+        for start in 0..100 {
+            for end in start..=100 {
+                let mut iter = Iter::new(Some(&node), start, end);
+                assert_eq!(iter.len(), end - start);
+
+                let mut front = vec![];
+                let mut back = vec![];
+                while let Some((k, _)) = iter.next() {
+                    front.push(*k);
+                    match iter.next_back() {
+                        Some((k, _)) => back.push(*k),
+                        None => break,
+                    }
+                }
+                front.extend(back.into_iter().rev());
+                assert_eq!(front, (start as i32..end as i32).collect::<Vec<_>>());
+            }
+        }
+    }
+}
@@ -0,0 +1,269 @@
+/// join subtrees of different height with a key value in between, the
+/// building block of split and concatenation
+use super::insert::InsertResult;
+use super::node::Node;
+use crate::{BTreeConfig, Summary};
+use std::sync::Arc;
+
+/// a subtree used as a standalone tree, its root may hold fewer key values
+/// than config's min
+pub(crate) struct Subtree<K, V, S = ()> {
+    pub node: Arc<Node<K, V, S>>,
+    /// leaf is at height 0
+    pub height: usize,
+}
+
+impl<K, V, S> Node<K, V, S> {
+    pub(crate) fn height(&self) -> usize {
+        let mut node = self;
+        let mut height = 0;
+        while !node.is_leaf() {
+            node = &node.children[0];
+            height += 1;
+        }
+        height
+    }
+}
+
+impl<K: Clone, V: Clone, S: Summary<K, V>> Subtree<K, V, S> {
+    /// subtree from a node's key values and children, if key values are
+    /// empty, the only child is the subtree
+    pub fn from_parts(
+        key_values: Vec<(K, V)>,
+        mut children: Vec<Arc<Node<K, V, S>>>,
+        height: usize,
+    ) -> Option<Self> {
+        if !key_values.is_empty() {
+            Some(Self {
+                node: Arc::new(Node::new_with_key_values(key_values, children)),
+                height,
+            })
+        } else if !children.is_empty() {
+            Some(Self {
+                node: children.remove(0),
+                height: height - 1,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// all key values in left, then key_value, then all key values in right
+    pub fn join(
+        left: Option<Self>,
+        key_value: (K, V),
+        right: Option<Self>,
+        config: &BTreeConfig,
+    ) -> Self {
+        match (left, right) {
+            (None, None) => Self {
+                node: Arc::new(Node::new_with_key_values(vec![key_value], vec![])),
+                height: 0,
+            },
+            (Some(mut left), None) => {
+                let (path, idx) = left.node.right_most_path();
+                let root = Arc::make_mut(&mut left.node);
+                let insert_result =
+                    root.insert_at_path(&path, idx, key_value.0, key_value.1, config);
+                left.grow(insert_result)
+            }
+            (None, Some(mut right)) => {
+                let path = vec![0; right.height];
+                let root = Arc::make_mut(&mut right.node);
+                let insert_result = root.insert_at_path(&path, 0, key_value.0, key_value.1, config);
+                right.grow(insert_result)
+            }
+            (Some(left), Some(right)) if left.height == right.height => {
+                let height = left.height;
+                let mut root =
+                    Node::new_with_key_values(vec![key_value], vec![left.node, right.node]);
+                root.merge_or_redistribute(0, config);
+                if root.key_values.is_empty() {
+                    Self {
+                        node: root.children.remove(0),
+                        height,
+                    }
+                } else {
+                    Self {
+                        node: Arc::new(root),
+                        height: height + 1,
+                    }
+                }
+            }
+            (Some(mut left), Some(right)) if left.height > right.height => {
+                let height = left.height;
+                let root = Arc::make_mut(&mut left.node);
+                let insert_result = root.join_right(height, key_value, right, config);
+                left.grow(insert_result)
+            }
+            (Some(left), Some(mut right)) => {
+                let height = right.height;
+                let root = Arc::make_mut(&mut right.node);
+                let insert_result = root.join_left(height, left, key_value, config);
+                right.grow(insert_result)
+            }
+        }
+    }
+
+    /// all key values in left, then all key values in right
+    pub fn concat(left: Option<Self>, right: Option<Self>, config: &BTreeConfig) -> Option<Self> {
+        let (key_value, right) = match right {
+            Some(right) => right.pop_first(config),
+            None => return left,
+        };
+        Some(Self::join(left, key_value, right, config))
+    }
+
+    /// remove the first key value, None is returned if subtree becomes empty
+    pub fn pop_first(mut self, config: &BTreeConfig) -> ((K, V), Option<Self>) {
+        let root = Arc::make_mut(&mut self.node);
+        let key_value = root.delete_by_offset(0, config).unwrap();
+
+        if root.count == 0 {
+            (key_value, None)
+        } else if root.key_values.is_empty() {
+            let child = root.children.remove(0);
+            let subtree = Self {
+                node: child,
+                height: self.height - 1,
+            };
+            (key_value, Some(subtree))
+        } else {
+            (key_value, Some(self))
+        }
+    }
+
+    /// make a new root if root splitted
+    fn grow(self, insert_result: InsertResult<K, V, S>) -> Self {
+        match insert_result {
+            InsertResult::Splited {
+                new_k_v,
+                new_l,
+                new_r,
+            } => Self {
+                node: Arc::new(Node::new_with_key_values(vec![new_k_v], vec![new_l, new_r])),
+                height: self.height + 1,
+            },
+            InsertResult::NotSplited { .. } => self,
+        }
+    }
+}
+
+impl<K: Clone, V: Clone, S: Summary<K, V>> Node<K, V, S> {
+    /// child indexes to the right most leaf, and the index after its last key
+    /// value
+    pub(crate) fn right_most_path(&self) -> (Vec<usize>, usize) {
+        let mut node = self;
+        let mut path = vec![];
+        while !node.is_leaf() {
+            path.push(node.children.len() - 1);
+            node = node.children.last().unwrap();
+        }
+        (path, node.key_values.len())
+    }
+
+    /// append key_value and right subtree along the right spine, self is at
+    /// height which is larger than right's
+    fn join_right(
+        &mut self,
+        height: usize,
+        key_value: (K, V),
+        right: Subtree<K, V, S>,
+        config: &BTreeConfig,
+    ) -> InsertResult<K, V, S> {
+        self.count += right.node.count + 1;
+
+        if height == right.height + 1 {
+            self.key_values.push(key_value);
+            self.children.push(right.node);
+            self.merge_or_redistribute(self.key_values.len() - 1, config);
+        } else {
+            let child_idx = self.children.len() - 1;
+            let child = Arc::make_mut(&mut self.children[child_idx]);
+            if let InsertResult::Splited {
+                new_k_v,
+                new_l,
+                new_r,
+            } = child.join_right(height - 1, key_value, right, config)
+            {
+                self.key_values.push(new_k_v);
+                self.children[child_idx] = new_l;
+                self.children.push(new_r);
+            }
+        }
+
+        self.split_if_overflow(true, config)
+    }
+
+    /// prepend left subtree and key_value along the left spine, self is at
+    /// height which is larger than left's
+    fn join_left(
+        &mut self,
+        height: usize,
+        left: Subtree<K, V, S>,
+        key_value: (K, V),
+        config: &BTreeConfig,
+    ) -> InsertResult<K, V, S> {
+        self.count += left.node.count + 1;
+
+        if height == left.height + 1 {
+            self.key_values.insert(0, key_value);
+            self.children.insert(0, left.node);
+            self.merge_or_redistribute(0, config);
+        } else {
+            let child = Arc::make_mut(&mut self.children[0]);
+            if let InsertResult::Splited {
+                new_k_v,
+                new_l,
+                new_r,
+            } = child.join_left(height - 1, left, key_value, config)
+            {
+                self.key_values.insert(0, new_k_v);
+                self.children[0] = new_r;
+                self.children.insert(0, new_l);
+            }
+        }
+
+        self.split_if_overflow(true, config)
+    }
+
+    /// if child at idx or idx + 1 is under size, merge them with the key
+    /// value in between. If merged node is too large, split it evenly.
+    /// Unlike rebalance, the under sized child can be far below min size.
+    pub(crate) fn merge_or_redistribute(&mut self, idx: usize, config: &BTreeConfig) {
+        if !config.node_under_size(self.children[idx].key_values.len())
+            && !config.node_under_size(self.children[idx + 1].key_values.len())
+        {
+            return;
+        }
+
+        let right = self.children.remove(idx + 1);
+        let left = &self.children[idx];
+        let mut key_values = left.key_values.clone();
+        key_values.push(self.key_values.remove(idx));
+        key_values.extend(right.key_values.iter().cloned());
+        let mut children = left.children.clone();
+        children.extend(right.children.iter().cloned());
+
+        if !config.node_should_split(key_values.len()) {
+            self.children[idx] = Arc::new(Self::new_with_key_values(key_values, children));
+            return;
+        }
+
+        let split_at = key_values.len() / 2;
+        let right_key_values = key_values.split_off(split_at + 1);
+        let key_value = key_values.pop().unwrap();
+        let right_children = if children.is_empty() {
+            vec![]
+        } else {
+            children.split_off(split_at + 1)
+        };
+
+        self.key_values.insert(idx, key_value);
+        self.children[idx] = Arc::new(Self::new_with_key_values(key_values, children));
+        self.children.insert(
+            idx + 1,
+            Arc::new(Self::new_with_key_values(right_key_values, right_children)),
+        );
+    }
+}
@@ -1,5 +1,13 @@
+pub mod algebra;
+pub mod build;
 pub mod delete;
+pub mod diff;
 pub mod find;
 pub mod insert;
+pub mod iter;
+pub mod join;
+#[allow(clippy::module_inception)]
 pub mod node;
+pub mod retain;
+pub mod split;
 pub mod visit;
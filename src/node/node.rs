@@ -1,14 +1,29 @@
+use crate::{Comparator, Summary};
+use std::borrow::Borrow;
 use std::fmt::Debug;
 use std::sync::Arc;
 
 /// Node is the tree node, root, branch and leaf node are same
-pub struct Node<K, V> {
+pub struct Node<K, V, S = ()> {
     pub(crate) key_values: Vec<(K, V)>,
-    pub(crate) children: Vec<Arc<Node<K, V>>>,
+    pub(crate) children: Vec<Arc<Node<K, V, S>>>,
     pub(crate) count: usize,
+    /// summary of all key values in this subtree, see `Summary`
+    pub(crate) summary: S,
 }
 
-impl<K: Debug, V: Debug> Debug for Node<K, V> {
+/// where a key is, or should be inserted, in tree
+pub(crate) struct KeyPosition {
+    /// child indexes walked from root to the node
+    pub path: Vec<usize>,
+    /// index in node's key_values, Err if key not exists. For Err, node is
+    /// always a leaf
+    pub idx: Result<usize, usize>,
+    /// offset of the key in tree, or the offset it would be inserted at
+    pub offset: usize,
+}
+
+impl<K: Debug, V: Debug, S> Debug for Node<K, V, S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Node")
             .field("count", &self.count)
@@ -18,55 +33,259 @@ impl<K: Debug, V: Debug> Debug for Node<K, V> {
     }
 }
 
-impl<K: Clone, V: Clone> Clone for Node<K, V> {
+impl<K: Clone, V: Clone, S: Clone> Clone for Node<K, V, S> {
     fn clone(&self) -> Self {
         Self {
             key_values: self.key_values.clone(),
             children: self.children.clone(),
             count: self.count,
+            summary: self.summary.clone(),
         }
     }
 }
 
-impl<K, V> Node<K, V> {
+impl<K, V, S> Node<K, V, S> {
     pub(crate) fn is_leaf(&self) -> bool {
         self.children.is_empty()
     }
 }
 
-impl<K: Ord + Clone, V: Clone> Node<K, V> {
+impl<K: Clone, V: Clone, S: Summary<K, V>> Node<K, V, S> {
     #[cfg(test)]
     pub(crate) fn new() -> Self {
         Self {
             key_values: vec![],
             children: vec![],
             count: 0,
+            summary: S::empty(),
         }
     }
 
     pub(crate) fn new_with_key_values(key_values: Vec<(K, V)>, children: Vec<Arc<Self>>) -> Self {
         let count = key_values.len() + children.iter().fold(0, |a, c| a + c.count);
-        Self {
+        let mut node = Self {
             key_values,
             children,
             count,
+            summary: S::empty(),
+        };
+        node.refresh_summary();
+        node
+    }
+
+    /// recompute summary from key values and children's summaries, called
+    /// whenever key values in this subtree change
+    pub(crate) fn refresh_summary(&mut self) {
+        let mut summary = S::empty();
+        for (idx, (key, value)) in self.key_values.iter().enumerate() {
+            if let Some(child) = self.children.get(idx) {
+                summary.combine(&child.summary);
+            }
+            summary.combine(&S::from_key_value(key, value));
+        }
+        if let Some(child) = self.children.get(self.key_values.len()) {
+            summary.combine(&child.summary);
         }
+        self.summary = summary;
     }
 
-    pub fn get_by_key(&self, key: &K) -> Option<&V> {
-        match self.key_values.binary_search_by(|(k, _)| k.cmp(key)) {
+    /// binary search key in this node's key values
+    pub(crate) fn search<Q: ?Sized, C: Comparator<Q>>(
+        &self,
+        key: &Q,
+        comparator: &C,
+    ) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+    {
+        self.key_values
+            .binary_search_by(|(k, _)| comparator.cmp(k.borrow(), key))
+    }
+
+    pub fn get_by_key<Q: ?Sized, C: Comparator<Q>>(&self, key: &Q, comparator: &C) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        match self.search(key, comparator) {
             Ok(idx) => Some(&self.key_values[idx].1),
             Err(idx) => {
                 if self.is_leaf() {
                     None
                 } else {
                     let child = &self.children[idx];
-                    child.get_by_key(key)
+                    child.get_by_key(key, comparator)
+                }
+            }
+        }
+    }
+
+    /// offset of key, or the offset it would be inserted at if not exists
+    pub fn rank<Q: ?Sized, C: Comparator<Q>>(&self, key: &Q, comparator: &C) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+    {
+        let mut node = self;
+        let mut offset = 0;
+        loop {
+            let search_result = node.search(key, comparator);
+            let idx = match search_result {
+                Ok(idx) | Err(idx) => idx,
+            };
+
+            offset += idx;
+            if node.is_leaf() {
+                return search_result.map(|_| offset).map_err(|_| offset);
+            }
+
+            offset += node.children[..idx].iter().fold(0, |a, c| a + c.count);
+            match search_result {
+                Ok(_) => return Ok(offset + node.children[idx].count),
+                Err(_) => node = &node.children[idx],
+            }
+        }
+    }
+
+    /// locate key with one descent, see `KeyPosition`
+    pub(crate) fn key_position<Q: ?Sized, C: Comparator<Q>>(
+        &self,
+        key: &Q,
+        comparator: &C,
+    ) -> KeyPosition
+    where
+        K: Borrow<Q>,
+    {
+        let mut node = self;
+        let mut path = vec![];
+        let mut offset = 0;
+        let idx = loop {
+            let search_result = node.search(key, comparator);
+            let idx = match search_result {
+                Ok(idx) | Err(idx) => idx,
+            };
+
+            offset += idx;
+            if node.is_leaf() {
+                break search_result;
+            }
+
+            offset += node.children[..idx].iter().fold(0, |a, c| a + c.count);
+            match search_result {
+                Ok(_) => {
+                    offset += node.children[idx].count;
+                    break search_result;
+                }
+                Err(_) => {
+                    path.push(idx);
+                    node = &node.children[idx];
                 }
             }
+        };
+
+        KeyPosition { path, idx, offset }
+    }
+
+    /// locate the key value covering position x, where measure maps a
+    /// summary to a size which adds up under combine. Key values of size 0
+    /// are never located. Returns the position, the summary of all key
+    /// values before it and the key value itself, None if x is not less than
+    /// the total size.
+    pub(crate) fn locate_by_measure(
+        &self,
+        x: usize,
+        measure: impl Fn(&S) -> usize,
+    ) -> Option<(KeyPosition, S, &(K, V))> {
+        if measure(&self.summary) <= x {
+            return None;
+        }
+
+        let mut node = self;
+        let mut path = vec![];
+        let mut offset = 0;
+        let mut before = S::empty();
+        'descend: loop {
+            for (idx, key_value) in node.key_values.iter().enumerate() {
+                if let Some(child) = node.children.get(idx) {
+                    let mut with_child = before.clone();
+                    with_child.combine(&child.summary);
+                    if measure(&with_child) > x {
+                        path.push(idx);
+                        node = child;
+                        continue 'descend;
+                    }
+                    before = with_child;
+                    offset += child.count;
+                }
+
+                let mut with_key_value = before.clone();
+                with_key_value.combine(&S::from_key_value(&key_value.0, &key_value.1));
+                if measure(&with_key_value) > x {
+                    let position = KeyPosition {
+                        path,
+                        idx: Ok(idx),
+                        offset,
+                    };
+                    return Some((position, before, key_value));
+                }
+                before = with_key_value;
+                offset += 1;
+            }
+
+            // x is within this subtree, so it is in the last child
+            path.push(node.children.len().checked_sub(1)?);
+            node = node.children.last()?;
         }
     }
 
+    /// summary of key values in [start, end) offset range. Children fully
+    /// inside the range contribute their summary as is, so only the nodes on
+    /// the paths to both ends are visited.
+    pub(crate) fn fold_offsets(&self, start: usize, end: usize) -> S {
+        if start == 0 && end >= self.count {
+            return self.summary.clone();
+        }
+
+        let mut summary = S::empty();
+        let mut offset = 0;
+        for idx in 0..=self.key_values.len() {
+            if offset >= end {
+                break;
+            }
+            if let Some(child) = self.children.get(idx) {
+                let child_end = offset + child.count;
+                if start < child_end {
+                    let child_start = start.saturating_sub(offset);
+                    summary.combine(&child.fold_offsets(child_start, end.min(child_end) - offset));
+                }
+                offset = child_end;
+            }
+            if let Some((key, value)) = self.key_values.get(idx) {
+                if start <= offset && offset < end {
+                    summary.combine(&S::from_key_value(key, value));
+                }
+                offset += 1;
+            }
+        }
+        summary
+    }
+
+    /// update k,v at idx of the node located by path with f, then refresh
+    /// summaries on the path. Only nodes on the path are copied if shared.
+    pub(crate) fn update_at_path<R>(
+        &mut self,
+        path: &[usize],
+        idx: usize,
+        f: impl FnOnce(&mut (K, V)) -> R,
+    ) -> R {
+        let result = match path.split_first() {
+            None => f(&mut self.key_values[idx]),
+            Some((&child_idx, path)) => {
+                Arc::make_mut(&mut self.children[child_idx]).update_at_path(path, idx, f)
+            }
+        };
+        self.refresh_summary();
+        result
+    }
+
     /// get k,v at offset
     pub fn get_by_offset(&self, offset: usize) -> Option<&(K, V)> {
         if self.count <= offset {
@@ -100,10 +319,134 @@ impl<K: Ord + Clone, V: Clone> Node<K, V> {
     }
 }
 
+/// Mutable access to key values, only for nodes without summary since the
+/// summaries on the path can't be refreshed after the reference is released
+impl<K: Clone, V: Clone> Node<K, V> {
+    /// get mutable k,v at idx of the node located by path, only nodes on the
+    /// path are copied if shared
+    pub(crate) fn get_mut_at_path(&mut self, path: &[usize], idx: usize) -> &mut (K, V) {
+        let mut node = self;
+        for child_idx in path {
+            node = Arc::make_mut(&mut node.children[*child_idx]);
+        }
+        &mut node.key_values[idx]
+    }
+
+    /// get mutable k,v at offset, only nodes on the path are copied if shared
+    pub(crate) fn get_mut_by_offset(&mut self, offset: usize) -> Option<&mut (K, V)> {
+        if self.count <= offset {
+            return None;
+        }
+
+        if self.is_leaf() {
+            self.key_values.get_mut(offset)
+        } else {
+            let mut relative_offset = offset;
+
+            for idx in 0..self.key_values.len() {
+                let left_child_count = self.children[idx].count;
+                if left_child_count > relative_offset {
+                    let left_child = Arc::make_mut(&mut self.children[idx]);
+                    return left_child.get_mut_by_offset(relative_offset);
+                }
+
+                relative_offset -= left_child_count;
+
+                if relative_offset == 0 {
+                    return Some(&mut self.key_values[idx]);
+                }
+
+                relative_offset -= 1;
+            }
+
+            // check the last child
+            let last_child = Arc::make_mut(self.children.last_mut().unwrap());
+            last_child.get_mut_by_offset(relative_offset)
+        }
+    }
+}
+
+#[cfg(test)]
+impl<K: Ord + Clone + Debug, V: Clone, S: Summary<K, V>> Node<K, V, S> {
+    /// check ordering, count, occupancy and leaf depth, panic if broken
+    pub(crate) fn check_invariants(&self, config: &crate::BTreeConfig) {
+        self.check_invariants_inner(config, true, true, None, None);
+    }
+
+    /// same as check_invariants but keys are not required to be ordered, for
+    /// trees indexed by offset only
+    pub(crate) fn check_structure(&self, config: &crate::BTreeConfig) {
+        self.check_invariants_inner(config, true, false, None, None);
+    }
+
+    /// returns depth of leaves under this node
+    fn check_invariants_inner(
+        &self,
+        config: &crate::BTreeConfig,
+        is_root: bool,
+        ordered: bool,
+        lower: Option<&K>,
+        upper: Option<&K>,
+    ) -> usize {
+        assert!(!config.node_should_split(self.key_values.len()));
+        if is_root {
+            assert!(!self.key_values.is_empty());
+        } else {
+            assert!(!config.node_under_size(self.key_values.len()));
+        }
+
+        if ordered {
+            let keys = self.key_values.iter().map(|(k, _)| k);
+            let mut prev = lower;
+            for k in keys.chain(upper) {
+                if let Some(prev) = prev {
+                    assert!(prev < k, "{prev:?} should be less than {k:?}");
+                }
+                prev = Some(k);
+            }
+        }
+
+        let children_count = self.children.iter().fold(0, |a, c| a + c.count);
+        assert_eq!(self.count, self.key_values.len() + children_count);
+
+        if self.is_leaf() {
+            return 0;
+        }
+
+        assert_eq!(self.children.len(), self.key_values.len() + 1);
+        let mut depth = None;
+        for (idx, child) in self.children.iter().enumerate() {
+            let child_lower = if idx == 0 {
+                lower
+            } else {
+                Some(&self.key_values[idx - 1].0)
+            };
+            let child_upper = self.key_values.get(idx).map(|(k, _)| k).or(upper);
+            let child_depth =
+                child.check_invariants_inner(config, false, ordered, child_lower, child_upper);
+            assert_eq!(*depth.get_or_insert(child_depth), child_depth);
+        }
+        depth.unwrap() + 1
+    }
+}
+
+#[cfg(test)]
+impl<K: Clone, V: Clone, S: Summary<K, V> + PartialEq + Debug> Node<K, V, S> {
+    /// check every node's summary equals the one computed from scratch
+    pub(crate) fn check_summary(&self) {
+        for child in self.children.iter() {
+            child.check_summary();
+        }
+        let mut node = self.clone();
+        node.refresh_summary();
+        assert_eq!(node.summary, self.summary);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{node::insert::InsertResult, BTreeConfig};
+    use crate::{node::insert::InsertResult, BTreeConfig, OrdComparator};
 
     #[test]
     fn test_node() {
@@ -111,7 +454,7 @@ mod tests {
         let mut node = Node::<i32, i32>::new();
         let keys = (1..100i32).rev().collect::<Vec<_>>();
         for i in keys.clone() {
-            match node.insert(i, i * 100, &config) {
+            match node.insert(i, i * 100, &OrdComparator, &config) {
                 InsertResult::Splited {
                     new_k_v,
                     new_l,
@@ -126,7 +469,7 @@ mod tests {
         }
 
         for i in keys.iter() {
-            assert_eq!(*node.get_by_key(i).unwrap(), i * 100);
+            assert_eq!(*node.get_by_key(i, &OrdComparator).unwrap(), i * 100);
         }
 
         for i in 0..keys.len() {
@@ -0,0 +1,75 @@
+/// bulk removal by predicate. Each node is visited once, nodes where nothing
+/// is removed are returned as is, so they stay shared with snapshots.
+use super::join::Subtree;
+use crate::{BTreeConfig, Summary};
+use std::sync::Arc;
+
+impl<K: Clone, V: Clone, S: Summary<K, V>> Subtree<K, V, S> {
+    /// keep key values for which f returns true, f is called in key order.
+    /// None is returned if nothing is kept.
+    pub fn retain(self, f: &mut impl FnMut(&K, &V) -> bool, config: &BTreeConfig) -> Option<Self> {
+        let node = self.node;
+        let height = self.height;
+
+        let mut changed = false;
+        let mut pieces = Vec::with_capacity(node.children.len());
+        let mut keep = Vec::with_capacity(node.key_values.len());
+        for idx in 0..=node.key_values.len() {
+            if let Some(child) = node.children.get(idx) {
+                let piece = Self {
+                    node: child.clone(),
+                    height: height - 1,
+                }
+                .retain(f, config);
+                changed |= !matches!(&piece, Some(piece) if Arc::ptr_eq(&piece.node, child));
+                pieces.push(piece);
+            }
+            if let Some((key, value)) = node.key_values.get(idx) {
+                let kept = f(key, value);
+                changed |= !kept;
+                keep.push(kept);
+            }
+        }
+
+        if !changed {
+            return Some(Self { node, height });
+        }
+
+        let kept_key_values = || {
+            node.key_values
+                .iter()
+                .zip(keep.iter())
+                .filter(|(_, kept)| **kept)
+                .map(|(key_value, _)| key_value.clone())
+                .collect::<Vec<_>>()
+        };
+
+        if node.is_leaf() {
+            return Self::from_parts(kept_key_values(), vec![], 0);
+        }
+
+        // all key values kept and children still fit, only replace children
+        let fits = |piece: &Option<Self>| {
+            matches!(piece, Some(piece) if piece.height == height - 1
+                && !config.node_under_size(piece.node.key_values.len()))
+        };
+        if keep.iter().all(|kept| *kept) && pieces.iter().all(fits) {
+            let children = pieces.into_iter().map(|p| p.unwrap().node).collect();
+            return Self::from_parts(kept_key_values(), children, height);
+        }
+
+        // otherwise join the pieces back with kept key values in between
+        let mut result = None;
+        let mut pending_key_value = None;
+        for (idx, piece) in pieces.into_iter().enumerate() {
+            result = match pending_key_value.take() {
+                Some(key_value) => Some(Self::join(result, key_value, piece, config)),
+                None => Self::concat(result, piece, config),
+            };
+            if keep.get(idx) == Some(&true) {
+                pending_key_value = Some(node.key_values[idx].clone());
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,135 @@
+/// split a tree in two, untouched subtrees are shared with the original tree
+use super::join::Subtree;
+use super::node::Node;
+use crate::{BTreeConfig, Summary};
+
+/// result of split, all keys in left are less than matched, which is less
+/// than all keys in right
+pub(crate) struct SplitResult<K, V, S = ()> {
+    pub left: Option<Subtree<K, V, S>>,
+    pub matched: Option<(K, V)>,
+    pub right: Option<Subtree<K, V, S>>,
+}
+
+impl<K: Clone, V: Clone, S: Summary<K, V>> Subtree<K, V, S> {
+    /// split by locate, which tells for each node on the path from root,
+    /// `Ok(idx)` if key value at idx is the split point, or `Err(idx)` if
+    /// split point is in child at idx. For leaf, `Err(idx)` means split
+    /// before key value at idx.
+    pub fn split(
+        &self,
+        locate: &mut impl FnMut(&Node<K, V, S>) -> Result<usize, usize>,
+        config: &BTreeConfig,
+    ) -> SplitResult<K, V, S> {
+        let node = &self.node;
+        let height = self.height;
+
+        match locate(node) {
+            Ok(idx) => SplitResult {
+                left: Subtree::from_parts(
+                    node.key_values[..idx].to_vec(),
+                    children_slice(node, 0, idx + 1),
+                    height,
+                ),
+                matched: Some(node.key_values[idx].clone()),
+                right: Subtree::from_parts(
+                    node.key_values[idx + 1..].to_vec(),
+                    children_slice(node, idx + 1, node.children.len()),
+                    height,
+                ),
+            },
+            Err(idx) if node.is_leaf() => SplitResult {
+                left: Subtree::from_parts(node.key_values[..idx].to_vec(), vec![], 0),
+                matched: None,
+                right: Subtree::from_parts(node.key_values[idx..].to_vec(), vec![], 0),
+            },
+            Err(idx) => {
+                let child = Subtree {
+                    node: node.children[idx].clone(),
+                    height: height - 1,
+                };
+                let SplitResult {
+                    left: child_left,
+                    matched,
+                    right: child_right,
+                } = child.split(locate, config);
+
+                let left = if idx == 0 {
+                    child_left
+                } else {
+                    let left_part = Subtree::from_parts(
+                        node.key_values[..idx - 1].to_vec(),
+                        node.children[..idx].to_vec(),
+                        height,
+                    );
+                    let key_value = node.key_values[idx - 1].clone();
+                    Some(Subtree::join(left_part, key_value, child_left, config))
+                };
+
+                let right = if idx == node.key_values.len() {
+                    child_right
+                } else {
+                    let right_part = Subtree::from_parts(
+                        node.key_values[idx + 1..].to_vec(),
+                        node.children[idx + 1..].to_vec(),
+                        height,
+                    );
+                    let key_value = node.key_values[idx].clone();
+                    Some(Subtree::join(child_right, key_value, right_part, config))
+                };
+
+                SplitResult {
+                    left,
+                    matched,
+                    right,
+                }
+            }
+        }
+    }
+}
+
+fn children_slice<K, V, S>(
+    node: &Node<K, V, S>,
+    start: usize,
+    end: usize,
+) -> Vec<std::sync::Arc<Node<K, V, S>>> {
+    if node.is_leaf() {
+        vec![]
+    } else {
+        node.children[start..end].to_vec()
+    }
+}
+
+impl<K, V, S> Node<K, V, S> {
+    /// locate fn for split by offset, see `Subtree::split`
+    pub(crate) fn locate_offset(
+        mut offset: usize,
+    ) -> impl FnMut(&Node<K, V, S>) -> Result<usize, usize> {
+        move |node| {
+            if node.is_leaf() {
+                return if offset < node.key_values.len() {
+                    Ok(offset)
+                } else {
+                    Err(offset)
+                };
+            }
+
+            for idx in 0..node.key_values.len() {
+                let left_child_count = node.children[idx].count;
+                if left_child_count > offset {
+                    return Err(idx);
+                }
+
+                offset -= left_child_count;
+
+                if offset == 0 {
+                    return Ok(idx);
+                }
+
+                offset -= 1;
+            }
+
+            Err(node.key_values.len())
+        }
+    }
+}
@@ -15,15 +15,15 @@ pub struct VisitStack<'a, K, V> {
     pub stacks: Vec<(NodeProxy<'a, K, V>, usize)>,
 }
 
-pub(crate) fn visit_node<K, V>(
-    node: &Node<K, V>,
+pub(crate) fn visit_node<K, V, S>(
+    node: &Node<K, V, S>,
     visit_fn: &mut impl FnMut(&VisitStack<'_, K, V>),
 ) {
     visit_node_inner(node, visit_fn, 0, vec![]);
 }
 
-fn visit_node_inner<'a, K, V>(
-    node: &'a Node<K, V>,
+fn visit_node_inner<'a, K, V, S>(
+    node: &'a Node<K, V, S>,
     visit_fn: &mut impl FnMut(&VisitStack<'_, K, V>),
     depth: usize,
     stacks: Vec<(NodeProxy<'a, K, V>, usize)>,
@@ -0,0 +1,458 @@
+/// Persistent rope for text editing. Text is stored as chunks of at most
+/// `MAX_CHUNK_BYTES` bytes, the values of a keyless tree like `Sequence`.
+/// Every node keeps bytes, chars and lines of its subtree in its summary, so
+/// a position of any metric is located with one descent.
+use crate::node::iter::Iter;
+use crate::node::join::Subtree;
+use crate::node::node::Node;
+use crate::node::split::SplitResult;
+use crate::{BTree, BTreeConfig, Summary};
+use std::fmt::{Debug, Display};
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+const MAX_CHUNK_BYTES: usize = 512;
+
+/// sizes of a piece of text, lines is the number of '\n' in it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextSummary {
+    pub bytes: usize,
+    pub chars: usize,
+    pub lines: usize,
+}
+
+impl TextSummary {
+    fn of(text: &str) -> Self {
+        Self {
+            bytes: text.len(),
+            chars: text.chars().count(),
+            lines: text.bytes().filter(|b| *b == b'\n').count(),
+        }
+    }
+}
+
+impl Summary<(), Chunk> for TextSummary {
+    fn empty() -> Self {
+        Self::default()
+    }
+
+    fn from_key_value(_key: &(), chunk: &Chunk) -> Self {
+        chunk.summary
+    }
+
+    fn combine(&mut self, other: &Self) {
+        self.bytes += other.bytes;
+        self.chars += other.chars;
+        self.lines += other.lines;
+    }
+}
+
+/// a piece of text with its summary cached, so nodes don't scan it again
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    text: String,
+    summary: TextSummary,
+}
+
+impl Chunk {
+    fn new(text: String) -> Self {
+        let summary = TextSummary::of(&text);
+        Self { text, summary }
+    }
+
+    fn update(&mut self, f: impl FnOnce(&mut String)) {
+        f(&mut self.text);
+        self.summary = TextSummary::of(&self.text);
+    }
+}
+
+type TextTree = Subtree<(), Chunk, TextSummary>;
+
+/// Clones are O(1) and share chunks with the original, keep them as undo
+/// snapshots.
+#[derive(Clone)]
+pub struct Rope {
+    pub(crate) root: Option<Arc<Node<(), Chunk, TextSummary>>>,
+    config: BTreeConfig,
+}
+
+impl Debug for Rope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Rope").field(&self.to_string()).finish()
+    }
+}
+
+impl Display for Rope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        let mut rope = Self::new();
+        rope.root = build(text, &rope.config).map(|t| t.node);
+        rope
+    }
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Self::new_with_config(BTree::<(), Chunk>::default_config())
+    }
+
+    pub fn new_with_config(config: BTreeConfig) -> Self {
+        Self { root: None, config }
+    }
+
+    /// bytes, chars and lines of the whole text
+    pub fn summary(&self) -> TextSummary {
+        self.root.as_ref().map(|r| r.summary).unwrap_or_default()
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.summary().bytes
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.summary().chars
+    }
+
+    /// number of lines, which is one more than the number of '\n'
+    pub fn len_lines(&self) -> usize {
+        self.summary().lines + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// insert text at byte position. If the chunk at position has room, it
+    /// is edited in place and only nodes on its path are copied.
+    ///
+    /// # Panics
+    /// Panics if byte is larger than len or not on a char boundary.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::Rope;
+    ///
+    /// let mut rope = Rope::from("hello world");
+    /// let snapshot = rope.clone();
+    /// rope.insert(5, ",");
+    /// rope.remove(6..);
+    /// rope.insert(6, " rope\n");
+    /// assert_eq!(rope.to_string(), "hello, rope\n");
+    /// assert_eq!(snapshot.to_string(), "hello world");
+    /// ```
+    pub fn insert(&mut self, byte: usize, text: &str) {
+        assert!(byte <= self.len_bytes(), "byte out of bounds");
+        if let Some(root) = self.root.as_ref() {
+            // the chunk containing byte, or the last chunk when appending
+            let x = byte.min(root.summary.bytes - 1);
+            let (position, before, (_, chunk)) = root.locate_by_measure(x, |s| s.bytes).unwrap();
+            assert!(
+                chunk.text.is_char_boundary(byte - before.bytes),
+                "byte is not a char boundary"
+            );
+            if text.is_empty() {
+                return;
+            }
+            if chunk.text.len() + text.len() <= MAX_CHUNK_BYTES {
+                let root = Arc::make_mut(self.root.as_mut().unwrap());
+                root.update_at_path(&position.path, position.idx.unwrap(), |(_, chunk)| {
+                    chunk.update(|s| s.insert_str(byte - before.bytes, text))
+                });
+                return;
+            }
+        } else if text.is_empty() {
+            return;
+        }
+
+        let (left, right) = split_at_byte(self.take_subtree(), byte, &self.config);
+        let left = concat(left, build(text, &self.config), &self.config);
+        self.root = concat(left, right, &self.config).map(|t| t.node);
+    }
+
+    /// remove text in byte range, the outer parts are joined in O(log n)
+    ///
+    /// # Panics
+    /// Panics if range is out of bounds or not on char boundaries.
+    pub fn remove(&mut self, range: impl RangeBounds<usize>) {
+        let (start, end) = self.byte_range(range);
+        let (left, rest) = split_at_byte(self.take_subtree(), start, &self.config);
+        let (_, right) = split_at_byte(rest, end - start, &self.config);
+        self.root = concat(left, right, &self.config).map(|t| t.node);
+    }
+
+    /// text in byte range as a new rope, sharing nodes with self
+    ///
+    /// # Panics
+    /// Panics if range is out of bounds or not on char boundaries.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        let (start, end) = self.byte_range(range);
+        let (_, rest) = split_at_byte(self.subtree(), start, &self.config);
+        let (middle, _) = split_at_byte(rest, end - start, &self.config);
+        Self {
+            root: middle.map(|t| t.node),
+            config: self.config,
+        }
+    }
+
+    /// byte position where line starts, line is 0 based
+    ///
+    /// # Panics
+    /// Panics if line is not less than len_lines.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::Rope;
+    ///
+    /// let rope = Rope::from("one\ntwo\n");
+    /// assert_eq!(rope.len_lines(), 3);
+    /// assert_eq!(rope.line_to_byte(1), 4);
+    /// assert_eq!(rope.line_to_byte(2), 8);
+    /// assert_eq!(rope.byte_to_line(5), 1);
+    /// ```
+    pub fn line_to_byte(&self, line: usize) -> usize {
+        assert!(line < self.len_lines(), "line out of bounds");
+        if line == 0 {
+            return 0;
+        }
+
+        // line starts after the line-th '\n', count from 0
+        let (chunk, before) = self.chunk_by(line - 1, |s| s.lines);
+        let (idx, _) = chunk
+            .text
+            .match_indices('\n')
+            .nth(line - 1 - before.lines)
+            .unwrap();
+        before.bytes + idx + 1
+    }
+
+    /// line which byte position is in
+    ///
+    /// # Panics
+    /// Panics if byte is larger than len.
+    pub fn byte_to_line(&self, byte: usize) -> usize {
+        assert!(byte <= self.len_bytes(), "byte out of bounds");
+        if byte == self.len_bytes() {
+            return self.summary().lines;
+        }
+
+        let (chunk, before) = self.chunk_by(byte, |s| s.bytes);
+        let at = byte - before.bytes;
+        before.lines
+            + chunk.text.as_bytes()[..at]
+                .iter()
+                .filter(|b| **b == b'\n')
+                .count()
+    }
+
+    /// byte position of char at char_idx
+    ///
+    /// # Panics
+    /// Panics if char_idx is larger than len_chars.
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        assert!(char_idx <= self.len_chars(), "char out of bounds");
+        if char_idx == self.len_chars() {
+            return self.len_bytes();
+        }
+
+        let (chunk, before) = self.chunk_by(char_idx, |s| s.chars);
+        let (idx, _) = chunk
+            .text
+            .char_indices()
+            .nth(char_idx - before.chars)
+            .unwrap();
+        before.bytes + idx
+    }
+
+    /// index of char at byte position
+    ///
+    /// # Panics
+    /// Panics if byte is larger than len or not on a char boundary.
+    pub fn byte_to_char(&self, byte: usize) -> usize {
+        self.assert_char_boundary(byte);
+        if byte == self.len_bytes() {
+            return self.len_chars();
+        }
+
+        let (chunk, before) = self.chunk_by(byte, |s| s.bytes);
+        before.chars + chunk.text[..byte - before.bytes].chars().count()
+    }
+
+    /// iterate text chunks in order
+    pub fn chunks(&self) -> Chunks<'_> {
+        let count = self.root.as_ref().map(|r| r.count).unwrap_or(0);
+        Chunks {
+            inner: Iter::new(self.root.as_deref(), 0, count),
+        }
+    }
+
+    /// chunk covering position x of measure, and the summary before it.
+    /// Caller should make sure x is less than the total.
+    fn chunk_by(&self, x: usize, measure: impl Fn(&TextSummary) -> usize) -> (&Chunk, TextSummary) {
+        let root = self.root.as_ref().unwrap();
+        let (_, before, (_, chunk)) = root.locate_by_measure(x, measure).unwrap();
+        (chunk, before)
+    }
+
+    fn assert_char_boundary(&self, byte: usize) {
+        assert!(byte <= self.len_bytes(), "byte out of bounds");
+        if byte < self.len_bytes() {
+            let (chunk, before) = self.chunk_by(byte, |s| s.bytes);
+            assert!(
+                chunk.text.is_char_boundary(byte - before.bytes),
+                "byte is not a char boundary"
+            );
+        }
+    }
+
+    fn byte_range(&self, range: impl RangeBounds<usize>) -> (usize, usize) {
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start.checked_add(1).expect("byte out of bounds"),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => end.checked_add(1).expect("byte out of bounds"),
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => self.len_bytes(),
+        };
+        assert!(start <= end, "range start is larger than end");
+        self.assert_char_boundary(start);
+        self.assert_char_boundary(end);
+        (start, end)
+    }
+
+    fn subtree(&self) -> Option<TextTree> {
+        self.clone().take_subtree()
+    }
+
+    /// take root out as a subtree, leave self empty
+    fn take_subtree(&mut self) -> Option<TextTree> {
+        let node = self.root.take()?;
+        let height = node.height();
+        Some(Subtree { node, height })
+    }
+}
+
+/// tree of text split into chunks, None if text is empty
+fn build(text: &str, config: &BTreeConfig) -> Option<TextTree> {
+    let mut chunks = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut at = rest.len().min(MAX_CHUNK_BYTES);
+        while !rest.is_char_boundary(at) {
+            at -= 1;
+        }
+        let (head, tail) = rest.split_at(at);
+        chunks.push(((), Chunk::new(head.to_string())));
+        rest = tail;
+    }
+
+    let node = Node::build(chunks, config)?;
+    let height = node.height();
+    Some(Subtree {
+        node: Arc::new(node),
+        height,
+    })
+}
+
+/// split tree into text before byte and text from byte, the chunk containing
+/// byte is cut in two
+fn split_at_byte(
+    tree: Option<TextTree>,
+    byte: usize,
+    config: &BTreeConfig,
+) -> (Option<TextTree>, Option<TextTree>) {
+    let Some(tree) = tree else {
+        return (None, None);
+    };
+    let Some((position, before, _)) = tree.node.locate_by_measure(byte, |s| s.bytes) else {
+        return (Some(tree), None);
+    };
+
+    let SplitResult {
+        left,
+        matched,
+        right,
+    } = tree.split(&mut Node::locate_offset(position.offset), config);
+    let (_, chunk) = matched.unwrap();
+    let at = byte - before.bytes;
+    if at == 0 {
+        return (left, Some(Subtree::join(None, ((), chunk), right, config)));
+    }
+
+    let head = Chunk::new(chunk.text[..at].to_string());
+    let tail = Chunk::new(chunk.text[at..].to_string());
+    (
+        Some(Subtree::join(left, ((), head), None, config)),
+        Some(Subtree::join(None, ((), tail), right, config)),
+    )
+}
+
+/// text of left followed by text of right. The chunks at the seam are merged
+/// if they fit in one, so edits don't leave many small chunks behind.
+fn concat(
+    left: Option<TextTree>,
+    right: Option<TextTree>,
+    config: &BTreeConfig,
+) -> Option<TextTree> {
+    let (mut left, right) = match (left, right) {
+        (Some(left), Some(right)) => (left, right),
+        (left, right) => return Subtree::concat(left, right, config),
+    };
+
+    let (path, idx) = left.node.right_most_path();
+    let ((), first) = right.node.get_by_offset(0).unwrap();
+    let last_len = left
+        .node
+        .get_by_offset(left.node.count - 1)
+        .unwrap()
+        .1
+        .text
+        .len();
+    if last_len + first.text.len() > MAX_CHUNK_BYTES {
+        return Subtree::concat(Some(left), Some(right), config);
+    }
+
+    let (((), first), right) = right.pop_first(config);
+    Arc::make_mut(&mut left.node).update_at_path(&path, idx - 1, |(_, chunk)| {
+        chunk.update(|s| s.push_str(&first.text))
+    });
+    Subtree::concat(Some(left), right, config)
+}
+
+pub struct Chunks<'a> {
+    inner: Iter<'a, (), Chunk, TextSummary>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, chunk)| chunk.text.as_str())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Chunks<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, chunk)| chunk.text.as_str())
+    }
+}
+
+impl ExactSizeIterator for Chunks<'_> {}
+
+impl std::iter::FusedIterator for Chunks<'_> {}
@@ -0,0 +1,205 @@
+/// Persistent vector, a tree whose only key is the position. Key values are
+/// `((), T)`, every operation descends by children's count, so no key is
+/// ever compared.
+use crate::node::iter::Values;
+use crate::node::join::Subtree;
+use crate::node::node::Node;
+use crate::{BTree, BTreeConfig};
+use std::fmt::Debug;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+pub struct Sequence<T> {
+    pub(crate) tree: BTree<(), T>,
+}
+
+impl<T: Clone + Debug> Debug for Sequence<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.tree.values()).finish()
+    }
+}
+
+/// O(1) snapshot of the sequence, see `Clone` of `BTree`
+impl<T> Clone for Sequence<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+impl<T: Clone> Default for Sequence<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Sequence<T> {
+    pub fn new() -> Self {
+        Self { tree: BTree::new() }
+    }
+
+    pub fn new_with_config(config: BTreeConfig) -> Self {
+        Self {
+            tree: BTree::new_with_config(config),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.tree.get_by_offset(index).map(|(_, v)| v)
+    }
+
+    /// mutable reference to value at index, nodes on the path are copied if
+    /// shared with snapshots
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.tree.get_mut_by_offset(index).map(|(_, v)| v)
+    }
+
+    /// replace value at index, returns the old one. None if index is out of
+    /// bounds, the sequence is not changed then.
+    pub fn set(&mut self, index: usize, value: T) -> Option<T> {
+        self.get_mut(index).map(|v| std::mem::replace(v, value))
+    }
+
+    /// insert value at index, values after it are shifted right
+    ///
+    /// # Panics
+    /// Panics if index is larger than len.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::Sequence;
+    ///
+    /// let mut seq = Sequence::new();
+    /// seq.push_back('a');
+    /// seq.push_back('c');
+    /// seq.insert_at(1, 'b');
+    /// seq.push_front('_');
+    /// assert_eq!(seq.iter().collect::<String>(), "_abc");
+    /// assert_eq!(seq.remove_at(0), Some('_'));
+    /// assert_eq!(seq.get(2), Some(&'c'));
+    /// ```
+    pub fn insert_at(&mut self, index: usize, value: T) {
+        assert!(index <= self.len(), "index out of bounds");
+        match self.tree.root.as_mut() {
+            Some(root) => {
+                let root = Arc::make_mut(root);
+                let insert_result = root.insert_at_offset(index, (), value, &self.tree.config);
+                self.tree.grow_root(insert_result);
+            }
+            None => {
+                let root = Node::new_with_key_values(vec![((), value)], vec![]);
+                self.tree.root = Some(Arc::new(root));
+            }
+        }
+    }
+
+    /// remove value at index, values after it are shifted left
+    pub fn remove_at(&mut self, index: usize) -> Option<T> {
+        self.tree.delete_by_offset(index).map(|(_, v)| v)
+    }
+
+    pub fn push_front(&mut self, value: T) {
+        self.insert_at(0, value);
+    }
+
+    pub fn push_back(&mut self, value: T) {
+        self.insert_at(self.len(), value);
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.tree.pop_first().map(|(_, v)| v)
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.tree.pop_last().map(|(_, v)| v)
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.tree.last().map(|(_, v)| v)
+    }
+
+    /// values before index and values from index. Self is not changed, both
+    /// parts share nodes with it.
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::Sequence;
+    ///
+    /// let seq = (0..10).collect::<Sequence<i32>>();
+    /// let (head, tail) = seq.split_at(3);
+    /// assert_eq!(head.iter().copied().collect::<Vec<_>>(), vec![0, 1, 2]);
+    /// let joined = Sequence::concat(tail, head);
+    /// assert_eq!(joined.get(0), Some(&3));
+    /// assert_eq!(joined.get(9), Some(&2));
+    /// ```
+    pub fn split_at(&self, index: usize) -> (Self, Self) {
+        let mut head = self.tree.clone();
+        let tail = head.split_off_at(index);
+        (Self { tree: head }, Self { tree: tail })
+    }
+
+    /// values of left followed by values of right in O(log n), config of left
+    /// is kept
+    pub fn concat(mut left: Self, mut right: Self) -> Self {
+        left.append(&mut right);
+        left
+    }
+
+    /// move all values of other to the end of self in O(log n), leave other
+    /// empty. Config of self is kept, if other has a different max_degree it
+    /// is rebuilt under self's config first, which takes O(n) of other.
+    pub fn append(&mut self, other: &mut Self) {
+        let empty = other.tree.new_empty();
+        let mut other =
+            std::mem::replace(&mut other.tree, empty).rebuild_with_config(self.tree.config);
+        let joined = Subtree::concat(
+            self.tree.take_subtree(),
+            other.take_subtree(),
+            &self.tree.config,
+        );
+        self.tree.root = joined.map(|j| j.node);
+    }
+
+    pub fn iter(&self) -> Values<'_, (), T> {
+        self.tree.values()
+    }
+
+    /// iterate values in index range, the range is clamped to len
+    pub fn range(&self, range: impl RangeBounds<usize>) -> Values<'_, (), T> {
+        Values {
+            inner: self.tree.iter_offsets(range),
+        }
+    }
+}
+
+/// Builds the tree bottom up in O(n)
+impl<T: Clone> FromIterator<T> for Sequence<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut seq = Self::new();
+        let key_values = iter.into_iter().map(|v| ((), v)).collect();
+        seq.tree.root = Node::build(key_values, &seq.tree.config).map(Arc::new);
+        seq
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a Sequence<T> {
+    type Item = &'a T;
+    type IntoIter = Values<'a, (), T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,266 @@
+/// Ordered set on top of `BTree<K, ()>`. The unit value is zero sized, a
+/// node's `Vec<(K, ())>` has the same layout as `Vec<K>`, so nothing is
+/// stored per entry besides the key.
+use crate::node::find::{KeyRangeResult, PredicateResult};
+use crate::node::iter::Keys;
+use crate::{BTree, BTreeConfig, Comparator, OrdComparator, SortedIterError};
+use std::borrow::Borrow;
+use std::fmt::Debug;
+use std::ops::RangeBounds;
+
+pub struct BTreeSet<K, C = OrdComparator> {
+    map: BTree<K, (), C>,
+}
+
+impl<K: Debug, C> Debug for BTreeSet<K, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BTreeSet").field("map", &self.map).finish()
+    }
+}
+
+/// O(1) snapshot of the set, see `Clone` of `BTree`
+impl<K, C: Clone> Clone for BTreeSet<K, C> {
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+        }
+    }
+}
+
+impl<K: Clone, C: Comparator<K> + Clone + Default> Default for BTreeSet<K, C> {
+    fn default() -> Self {
+        Self {
+            map: BTree::default(),
+        }
+    }
+}
+
+/// sets ordered by key's `Ord`
+impl<K: Ord + Clone> BTreeSet<K> {
+    pub fn new() -> Self {
+        Self { map: BTree::new() }
+    }
+
+    pub fn new_with_config(config: BTreeConfig) -> Self {
+        Self {
+            map: BTree::new_with_config(config),
+        }
+    }
+
+    /// build set from strictly ascending keys in O(n)
+    pub fn from_sorted_iter(
+        iter: impl IntoIterator<Item = K>,
+        config: BTreeConfig,
+    ) -> Result<Self, SortedIterError> {
+        let map = BTree::from_sorted_iter(iter.into_iter().map(|k| (k, ())), config)?;
+        Ok(Self { map })
+    }
+}
+
+impl<K: Clone, C: Comparator<K> + Clone> BTreeSet<K, C> {
+    /// create a new set ordered by comparator, see `BTree::new_with_comparator`
+    pub fn new_with_comparator(config: BTreeConfig, comparator: C) -> Self {
+        Self {
+            map: BTree::new_with_comparator(config, comparator),
+        }
+    }
+
+    /// insert key, returns whether it is newly added
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTreeSet;
+    ///
+    /// let mut set = BTreeSet::new();
+    /// assert!(set.insert(3));
+    /// assert!(!set.insert(3));
+    /// assert!(set.contains(&3));
+    /// assert!(set.remove(&3));
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn insert(&mut self, key: K) -> bool {
+        self.map.insert(key, ())
+    }
+
+    /// remove key, returns whether it was in set
+    pub fn remove<Q: ?Sized>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.map.delete_by_key(key).is_some()
+    }
+
+    /// remove and return the key in set which equals key
+    pub fn take<Q: ?Sized>(&mut self, key: &Q) -> Option<K>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.map.delete_by_key(key).map(|(k, _)| k)
+    }
+
+    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.map.get_by_key(key).is_some()
+    }
+
+    /// offset of key, see `BTree::rank`
+    pub fn rank<Q: ?Sized>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        self.map.rank(key)
+    }
+
+    /// key at offset
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTreeSet;
+    ///
+    /// let set = [30, 10, 20].into_iter().collect::<BTreeSet<i32>>();
+    /// assert_eq!(set.select(1), Some(&20));
+    /// assert_eq!(set.rank(&20), Ok(1));
+    /// assert_eq!(set.select(3), None);
+    /// ```
+    pub fn select(&self, offset: usize) -> Option<&K> {
+        self.map.get_by_offset(offset).map(|(k, _)| k)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn first(&self) -> Option<&K> {
+        self.map.first().map(|(k, _)| k)
+    }
+
+    pub fn last(&self) -> Option<&K> {
+        self.map.last().map(|(k, _)| k)
+    }
+
+    pub fn pop_first(&mut self) -> Option<K> {
+        self.map.pop_first().map(|(k, _)| k)
+    }
+
+    pub fn pop_last(&mut self) -> Option<K> {
+        self.map.pop_last().map(|(k, _)| k)
+    }
+
+    /// iterate keys in order
+    pub fn iter(&self) -> Keys<'_, K, ()> {
+        self.map.keys()
+    }
+
+    /// iterate keys in range
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTreeSet;
+    ///
+    /// let set = (0..10).collect::<BTreeSet<i32>>();
+    /// assert_eq!(set.range(3..6).copied().collect::<Vec<_>>(), vec![3, 4, 5]);
+    /// ```
+    pub fn range<Q: ?Sized, R: RangeBounds<Q>>(&self, range: R) -> Keys<'_, K, ()>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        Keys {
+            inner: self.map.range(range),
+        }
+    }
+
+    /// iterate keys in offset range, see `BTree::iter_offsets`
+    pub fn iter_offsets(&self, range: impl RangeBounds<usize>) -> Keys<'_, K, ()> {
+        Keys {
+            inner: self.map.iter_offsets(range),
+        }
+    }
+
+    /// split set at key, self keeps keys less than key, returns the rest
+    pub fn split_off<Q: ?Sized>(&mut self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+    {
+        Self {
+            map: self.map.split_off(key),
+        }
+    }
+
+    /// move all keys of other into self, leave other empty
+    pub fn append(&mut self, other: &mut Self) {
+        self.map.append(&mut other.map);
+    }
+
+    /// keys in either set, shared subtrees are reused without visiting
+    ///
+    /// # Examples
+    /// ```
+    /// use imord2::BTreeSet;
+    ///
+    /// let a = [1, 2, 3].into_iter().collect::<BTreeSet<i32>>();
+    /// let b = [2, 3, 4].into_iter().collect::<BTreeSet<i32>>();
+    /// assert_eq!(a.union(&b).iter().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    /// assert_eq!(a.intersection(&b).iter().copied().collect::<Vec<_>>(), vec![2, 3]);
+    /// assert_eq!(a.difference(&b).iter().copied().collect::<Vec<_>>(), vec![1]);
+    /// assert_eq!(a.symmetric_difference(&b).iter().copied().collect::<Vec<_>>(), vec![1, 4]);
+    /// ```
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            map: self.map.union(&other.map, |_, _, _| ()),
+        }
+    }
+
+    /// keys in both sets
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self {
+            map: self.map.intersection(&other.map),
+        }
+    }
+
+    /// keys in self but not in other
+    pub fn difference(&self, other: &Self) -> Self {
+        Self {
+            map: self.map.difference(&other.map),
+        }
+    }
+
+    /// keys in exactly one of the sets
+    pub fn symmetric_difference(&self, other: &Self) -> Self {
+        Self {
+            map: self.map.symmetric_difference(&other.map),
+        }
+    }
+
+    pub fn find_key_range(&self, pred: impl Fn(&K) -> PredicateResult) -> KeyRangeResult<'_, K> {
+        self.map.find_key_range(pred)
+    }
+}
+
+impl<K: Clone, C: Comparator<K> + Clone + Default> FromIterator<K> for BTreeSet<K, C> {
+    fn from_iter<T: IntoIterator<Item = K>>(iter: T) -> Self {
+        Self {
+            map: iter.into_iter().map(|k| (k, ())).collect(),
+        }
+    }
+}
+
+impl<'a, K: Clone, C: Comparator<K> + Clone> IntoIterator for &'a BTreeSet<K, C> {
+    type Item = &'a K;
+    type IntoIter = Keys<'a, K, ()>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,283 @@
+/// summaries of subtrees, kept in every node next to `count`
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ops::AddAssign;
+
+/// aggregate of key values in a subtree, e.g. sums of some metrics. A
+/// node's summary is its children's and key values' summaries combined in
+/// key order, so `combine` must be associative, with `empty` as identity.
+pub trait Summary<K, V>: Clone {
+    /// summary of no key value, combining with it changes nothing
+    fn empty() -> Self;
+
+    fn from_key_value(key: &K, value: &V) -> Self;
+
+    /// combine with the summary of key values right after self's
+    fn combine(&mut self, other: &Self);
+}
+
+/// no summary, plain trees only keep `count`
+impl<K, V> Summary<K, V> for () {
+    fn empty() -> Self {}
+
+    fn from_key_value(_key: &K, _value: &V) -> Self {}
+
+    fn combine(&mut self, _other: &Self) {}
+}
+
+/// weight of a key value, e.g. its byte size or row height in pixels. The
+/// count kept in every node is the case where each key value weighs 1.
+pub trait Measure<K, V> {
+    fn measure(key: &K, value: &V) -> usize;
+}
+
+/// summary summing the measure of key values, see `BTree::get_by_measure`
+pub struct Measured<M> {
+    total: usize,
+    measure: PhantomData<fn() -> M>,
+}
+
+impl<M> Measured<M> {
+    /// sum of measures of the key values summarized
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+impl<M> Debug for Measured<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Measured")
+            .field("total", &self.total)
+            .finish()
+    }
+}
+
+impl<M> Clone for Measured<M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M> Copy for Measured<M> {}
+
+impl<M> PartialEq for Measured<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.total == other.total
+    }
+}
+
+impl<M> Eq for Measured<M> {}
+
+impl<K, V, M: Measure<K, V>> Summary<K, V> for Measured<M> {
+    fn empty() -> Self {
+        Self {
+            total: 0,
+            measure: PhantomData,
+        }
+    }
+
+    fn from_key_value(key: &K, value: &V) -> Self {
+        Self {
+            total: M::measure(key, value),
+            measure: PhantomData,
+        }
+    }
+
+    fn combine(&mut self, other: &Self) {
+        self.total += other.total;
+    }
+}
+
+/// two summaries kept side by side, e.g. sum and max of the same tree
+impl<K, V, A: Summary<K, V>, B: Summary<K, V>> Summary<K, V> for (A, B) {
+    fn empty() -> Self {
+        (A::empty(), B::empty())
+    }
+
+    fn from_key_value(key: &K, value: &V) -> Self {
+        (A::from_key_value(key, value), B::from_key_value(key, value))
+    }
+
+    fn combine(&mut self, other: &Self) {
+        self.0.combine(&other.0);
+        self.1.combine(&other.1);
+    }
+}
+
+/// value of a key value aggregated by `Sum`, `Min` and `Max`
+pub trait Field<K, V> {
+    type Value;
+
+    fn get(key: &K, value: &V) -> Self::Value;
+}
+
+/// sum of a field over key values
+pub struct Sum<T, F> {
+    value: T,
+    field: PhantomData<fn() -> F>,
+}
+
+impl<T, F> Sum<T, F> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Debug, F> Debug for Sum<T, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Sum").field(&self.value).finish()
+    }
+}
+
+impl<T: Clone, F> Clone for Sum<T, F> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            field: PhantomData,
+        }
+    }
+}
+
+impl<T: PartialEq, F> PartialEq for Sum<T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<K, V, T, F> Summary<K, V> for Sum<T, F>
+where
+    T: Clone + Default + for<'a> AddAssign<&'a T>,
+    F: Field<K, V, Value = T>,
+{
+    fn empty() -> Self {
+        Self {
+            value: T::default(),
+            field: PhantomData,
+        }
+    }
+
+    fn from_key_value(key: &K, value: &V) -> Self {
+        Self {
+            value: F::get(key, value),
+            field: PhantomData,
+        }
+    }
+
+    fn combine(&mut self, other: &Self) {
+        self.value += &other.value;
+    }
+}
+
+/// min of a field over key values, None if there is no key value
+pub struct Min<T, F> {
+    value: Option<T>,
+    field: PhantomData<fn() -> F>,
+}
+
+impl<T, F> Min<T, F> {
+    pub fn value(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+}
+
+impl<T: Debug, F> Debug for Min<T, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Min").field(&self.value).finish()
+    }
+}
+
+impl<T: Clone, F> Clone for Min<T, F> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            field: PhantomData,
+        }
+    }
+}
+
+impl<T: PartialEq, F> PartialEq for Min<T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<K, V, T: Ord + Clone, F: Field<K, V, Value = T>> Summary<K, V> for Min<T, F> {
+    fn empty() -> Self {
+        Self {
+            value: None,
+            field: PhantomData,
+        }
+    }
+
+    fn from_key_value(key: &K, value: &V) -> Self {
+        Self {
+            value: Some(F::get(key, value)),
+            field: PhantomData,
+        }
+    }
+
+    fn combine(&mut self, other: &Self) {
+        match (&self.value, &other.value) {
+            (Some(value), Some(other_value)) if other_value >= value => {}
+            (_, None) => {}
+            _ => self.value = other.value.clone(),
+        }
+    }
+}
+
+/// max of a field over key values, None if there is no key value
+pub struct Max<T, F> {
+    value: Option<T>,
+    field: PhantomData<fn() -> F>,
+}
+
+impl<T, F> Max<T, F> {
+    pub fn value(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+}
+
+impl<T: Debug, F> Debug for Max<T, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Max").field(&self.value).finish()
+    }
+}
+
+impl<T: Clone, F> Clone for Max<T, F> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            field: PhantomData,
+        }
+    }
+}
+
+impl<T: PartialEq, F> PartialEq for Max<T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<K, V, T: Ord + Clone, F: Field<K, V, Value = T>> Summary<K, V> for Max<T, F> {
+    fn empty() -> Self {
+        Self {
+            value: None,
+            field: PhantomData,
+        }
+    }
+
+    fn from_key_value(key: &K, value: &V) -> Self {
+        Self {
+            value: Some(F::get(key, value)),
+            field: PhantomData,
+        }
+    }
+
+    fn combine(&mut self, other: &Self) {
+        match (&self.value, &other.value) {
+            (Some(value), Some(other_value)) if other_value <= value => {}
+            (_, None) => {}
+            _ => self.value = other.value.clone(),
+        }
+    }
+}